@@ -27,22 +27,29 @@ async function handleRequest(event) {
             }
         }
 
-        // Fully read body (synchronously) before calling Rust handler.
-        // For protection against excessive uploads, the maximum data upload size
-        // can be set in dash.cloudflare.com -> Network -> "Maximum Upload Size"
+        // The body is passed as a stream (or null), and is read only if the
+        // Rust handler requests it. For protection against excessive uploads,
+        // set max_body_size in ServiceConfig, and/or the maximum data upload size
+        // in dash.cloudflare.com -> Network -> "Maximum Upload Size"
         let input = new Map();
-        input.set("body", new Uint8Array(await request.arrayBuffer()));
+        input.set("body", request.body);
         input.set("method", request.method);
         input.set("url", request.url);
         input.set("headers", request.headers);
         input.set("event", event);
+        // environment bindings (vars, secrets, and KV namespaces) are globals
+        // in service-worker syntax. For module syntax, pass 'env' instead.
+        input.set("env", globalThis);
 
         // call rust handler, put results into a Response object
         result = await main_entry(input);
         var body_bin = result.get("body"); // Uint8Array
-        response = new Response(body_bin, {
-            status: result.get("status"),
+        var status = result.get("status");
+        response = new Response(status == 101 ? null : body_bin, {
+            status: status,
             headers: result.get("headers"),
+            webSocket: result.get("webSocket"), // client socket for websocket upgrade
+            encodeBody: result.get("encodeBody"), // "manual" if body was compressed by rust
         });
     } catch(error) {
         response = new Response("Error:" + error, {status: 200});
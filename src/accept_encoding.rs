@@ -0,0 +1,68 @@
+//! Accept-Encoding header parsing, shared by response compression and pre-compressed assets
+
+/// Returns the encodings from `supported` that are acceptable according to the
+/// Accept-Encoding header value, most preferred first, using q-values (default 1).
+/// "*" matches any encoding not listed explicitly, and "x-gzip" is the same as "gzip".
+/// Encodings with equal q-values are returned in the order of `supported`.
+pub(crate) fn accepted_encodings(accept: &str, supported: &[&'static str]) -> Vec<&'static str> {
+    let mut star_q = None;
+    let mut qs = vec![None; supported.len()];
+    for item in accept.split(',') {
+        let mut parts = item.split(';');
+        let name = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+        let q = parts
+            .filter_map(|p| {
+                let p = p.trim();
+                if p.starts_with("q=") || p.starts_with("Q=") {
+                    p[2..].trim().parse::<f32>().ok()
+                } else {
+                    None
+                }
+            })
+            .next()
+            .unwrap_or(1.0);
+        let name = match name.as_str() {
+            "*" => {
+                star_q = Some(q);
+                continue;
+            }
+            "x-gzip" => "gzip",
+            name => name,
+        };
+        if let Some(i) = supported.iter().position(|enc| *enc == name) {
+            qs[i] = Some(q);
+        }
+    }
+    let mut encodings = supported
+        .iter()
+        .zip(qs.iter())
+        .filter_map(|(enc, q)| match q.or(star_q) {
+            Some(q) if q > 0.0 => Some((*enc, q)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    // stable sort keeps the order of `supported` when q values are equal
+    encodings.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    encodings.into_iter().map(|(enc, _)| enc).collect()
+}
+
+#[test]
+fn accept_encoding_q_values() {
+    let supported = ["br", "gzip"];
+    assert_eq!(
+        accepted_encodings("gzip, deflate, br", &supported),
+        vec!["br", "gzip"]
+    );
+    assert_eq!(accepted_encodings("x-gzip", &supported), vec!["gzip"]);
+    assert_eq!(
+        accepted_encodings("br;q=0.5, gzip", &supported),
+        vec!["gzip", "br"]
+    );
+    assert_eq!(accepted_encodings("br;Q=0, *", &supported), vec!["gzip"]);
+    assert_eq!(
+        accepted_encodings("*;q=0.1", &supported),
+        vec!["br", "gzip"]
+    );
+    assert!(accepted_encodings("identity", &supported).is_empty());
+    assert!(accepted_encodings("", &supported).is_empty());
+}
@@ -0,0 +1,178 @@
+use bytes::Bytes;
+use kv_assets::AssetMetadata;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// Bounded in-memory LRU cache of static asset bodies, for use with
+/// [`StaticAssetHandler::with_cache`](crate::StaticAssetHandler::with_cache).
+///
+/// Entries are keyed by asset path and modified time, so a new version of an asset
+/// replaces the old one. When the cache exceeds its entry count or total size,
+/// least recently used entries are removed. Assets larger than the size limit are not cached.
+///
+/// The cache lasts as long as the worker isolate, so it should be created once,
+/// for example in a `thread_local!`, and shared with each request's handler:
+///```rust,ignore
+/// thread_local! {
+///     static ASSET_CACHE: Rc<AssetCache> = Rc::new(AssetCache::new(100, 4 * 1024 * 1024));
+/// }
+/// let handler = StaticAssetHandler::new(store)
+///     .with_cache(ASSET_CACHE.with(|cache| cache.clone()));
+///```
+#[derive(Debug)]
+pub struct AssetCache {
+    max_entries: usize,
+    max_bytes: usize,
+    inner: RefCell<LruState>,
+}
+
+#[derive(Debug, Default)]
+struct LruState {
+    /// path -> entry
+    entries: HashMap<String, Entry>,
+    /// last use -> path, oldest first
+    order: BTreeMap<u64, String>,
+    /// incremented on each use
+    tick: u64,
+    bytes: usize,
+    stats: AssetCacheStats,
+}
+
+#[derive(Debug)]
+struct Entry {
+    modified: u64,
+    data: Bytes,
+    last_used: u64,
+}
+
+/// Counters for an [`AssetCache`]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AssetCacheStats {
+    /// number of lookups that returned a cached body
+    pub hits: u64,
+    /// number of lookups that did not find the asset, or found an older version
+    pub misses: u64,
+    /// number of entries removed to stay within limits
+    pub evictions: u64,
+    /// current number of entries
+    pub entries: usize,
+    /// current total size of cached bodies, in bytes
+    pub bytes: usize,
+}
+
+impl fmt::Display for AssetCacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "hits:{} misses:{} evictions:{} entries:{} bytes:{}",
+            self.hits, self.misses, self.evictions, self.entries, self.bytes
+        )
+    }
+}
+
+impl AssetCache {
+    /// Creates a cache holding at most `max_entries` assets, and at most `max_bytes`
+    /// total bytes of asset content
+    pub fn new(max_entries: usize, max_bytes: usize) -> Self {
+        AssetCache {
+            max_entries,
+            max_bytes,
+            inner: RefCell::new(LruState::default()),
+        }
+    }
+
+    /// Returns the cached body of the asset, if it is cached with the same modified time
+    pub fn get(&self, md: &AssetMetadata) -> Option<Bytes> {
+        let mut state = self.inner.borrow_mut();
+        state.tick += 1;
+        let tick = state.tick;
+        let found = match state.entries.get_mut(&md.path) {
+            Some(entry) if entry.modified == md.modified => {
+                let prev = entry.last_used;
+                entry.last_used = tick;
+                Some((prev, entry.data.clone()))
+            }
+            _ => None,
+        };
+        match found {
+            Some((prev, data)) => {
+                state.order.remove(&prev);
+                state.order.insert(tick, md.path.clone());
+                state.stats.hits += 1;
+                Some(data)
+            }
+            None => {
+                state.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Adds the asset body to the cache, replacing any previous version,
+    /// and removes least recently used entries if the cache is over its limits
+    pub fn insert(&self, md: &AssetMetadata, data: Bytes) {
+        if data.len() > self.max_bytes || self.max_entries == 0 {
+            return;
+        }
+        let mut state = self.inner.borrow_mut();
+        state.remove(&md.path);
+        state.tick += 1;
+        let tick = state.tick;
+        state.bytes += data.len();
+        state.order.insert(tick, md.path.clone());
+        state.entries.insert(
+            md.path.clone(),
+            Entry {
+                modified: md.modified,
+                data,
+                last_used: tick,
+            },
+        );
+        while state.entries.len() > self.max_entries || state.bytes > self.max_bytes {
+            let oldest = match state.order.values().next() {
+                Some(path) => path.clone(),
+                None => break,
+            };
+            state.remove(&oldest);
+            state.stats.evictions += 1;
+        }
+    }
+
+    /// Removes all entries. Counters are not reset.
+    pub fn clear(&self) {
+        let mut state = self.inner.borrow_mut();
+        state.entries.clear();
+        state.order.clear();
+        state.bytes = 0;
+    }
+
+    /// Returns the number of cached assets
+    pub fn len(&self) -> usize {
+        self.inner.borrow().entries.len()
+    }
+
+    /// Returns true if the cache is empty
+    pub fn is_empty(&self) -> bool {
+        self.inner.borrow().entries.is_empty()
+    }
+
+    /// Returns hit/miss counters and current size, for logging
+    pub fn stats(&self) -> AssetCacheStats {
+        let state = self.inner.borrow();
+        AssetCacheStats {
+            entries: state.entries.len(),
+            bytes: state.bytes,
+            ..state.stats
+        }
+    }
+}
+
+impl LruState {
+    fn remove(&mut self, path: &str) {
+        if let Some(entry) = self.entries.remove(path) {
+            self.order.remove(&entry.last_used);
+            self.bytes -= entry.data.len();
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use crate::{Error, KvNamespace};
+use async_trait::async_trait;
+use bytes::Bytes;
+use kv_assets::{AssetIndex, AssetMetadata, KV};
+use std::cell::OnceCell;
+use std::collections::HashMap;
+
+/// Storage backend for [`StaticAssetHandler`](crate::StaticAssetHandler).
+/// Assets are found by path (without leading '/') in an index of metadata,
+/// and the metadata is used to read the content.
+#[async_trait(?Send)]
+pub trait AssetStore {
+    /// Returns metadata for the asset at the path, or None if there is no such asset.
+    /// This should be fast, and not require network access, since it is called
+    /// for requests that may not be for static assets.
+    fn lookup(&self, path: &str) -> Result<Option<AssetMetadata>, Error>;
+
+    /// Returns the content of the asset, using metadata returned from `lookup`
+    async fn get(&self, md: &AssetMetadata) -> Result<Bytes, Error>;
+
+    /// Returns the paths and metadata of all assets whose path starts with the prefix,
+    /// in any order. This is used for directory listings. The default implementation
+    /// returns an empty list, for stores that can't enumerate their assets.
+    fn list(&self, prefix: &str) -> Result<Vec<(String, AssetMetadata)>, Error> {
+        let _ = prefix;
+        Ok(Vec::new())
+    }
+}
+
+/// Returns the sha256 hash of the asset content, if it is part of the KV key,
+/// as in keys generated by [`IndexBuilder`](crate::IndexBuilder) (`css/site.<hash>.css`)
+pub(crate) fn key_sha256(key: &str) -> Option<[u8; 32]> {
+    let name = key.rsplit('/').next().unwrap_or(key);
+    name.split('.').skip(1).find_map(|part| {
+        let mut hash = [0u8; 32];
+        if part.len() == 64 && hex::decode_to_slice(part, &mut hash).is_ok() {
+            Some(hash)
+        } else {
+            None
+        }
+    })
+}
+
+/// Asset index (as generated by kv-assets), deserialized the first time it is used
+struct LazyIndex<'assets> {
+    bin: &'assets [u8],
+    map: OnceCell<AssetIndex>,
+}
+
+impl<'assets> LazyIndex<'assets> {
+    fn new(bin: &'assets [u8]) -> Self {
+        LazyIndex {
+            bin,
+            map: OnceCell::new(),
+        }
+    }
+
+    fn get(&self) -> Result<&AssetIndex, Error> {
+        if let Some(map) = self.map.get() {
+            return Ok(map);
+        }
+        let map = bincode::deserialize(self.bin).map_err(Error::DeserializeAssets)?;
+        Ok(self.map.get_or_init(|| map))
+    }
+
+    fn lookup(&self, path: &str) -> Result<Option<AssetMetadata>, Error> {
+        Ok(self.get()?.get(path).cloned())
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<(String, AssetMetadata)>, Error> {
+        Ok(self
+            .get()?
+            .iter()
+            .filter(|(path, _)| path.starts_with(prefix))
+            .map(|(path, md)| (path.clone(), md.clone()))
+            .collect())
+    }
+}
+
+/// Reads assets from Workers KV with the Cloudflare REST API.
+/// This requires an api token, and is slower than [`KvBindingAssets`],
+/// but it can be used outside a worker.
+pub struct KvRestAssets<'assets> {
+    index: LazyIndex<'assets>,
+    kv: KV,
+}
+
+impl<'assets> KvRestAssets<'assets> {
+    /// Initialize store
+    /// `index_bin` is the serialized AssetIndex, which will be deserialized lazily (if needed)
+    /// `account_id` is Cloudflare account id
+    /// `namespace_id` is cloudflare KV namespace id (the long hex string, not the friendly name)
+    /// `auth_token` Cloudflare api OAuth token
+    pub fn init(
+        index_bin: &'assets [u8],
+        account_id: &'_ str,
+        namespace_id: &'_ str,
+        auth_token: &'_ str,
+    ) -> Self {
+        KvRestAssets {
+            index: LazyIndex::new(index_bin),
+            kv: kv_assets::init_kv(account_id, namespace_id, auth_token),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl<'assets> AssetStore for KvRestAssets<'assets> {
+    fn lookup(&self, path: &str) -> Result<Option<AssetMetadata>, Error> {
+        self.index.lookup(path)
+    }
+
+    async fn get(&self, md: &AssetMetadata) -> Result<Bytes, Error> {
+        Ok(self.kv.get_kv_value(&md.path).await?)
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<(String, AssetMetadata)>, Error> {
+        self.index.list(prefix)
+    }
+}
+
+/// Reads assets from a Workers KV namespace binding (see [`Env::kv`](crate::Env::kv)).
+/// No api token is needed.
+pub struct KvBindingAssets<'assets> {
+    index: LazyIndex<'assets>,
+    kv: KvNamespace,
+}
+
+impl<'assets> KvBindingAssets<'assets> {
+    /// Initialize store
+    /// `index_bin` is the serialized AssetIndex, which will be deserialized lazily (if needed)
+    /// `kv` is the namespace containing the assets
+    pub fn init(index_bin: &'assets [u8], kv: KvNamespace) -> Self {
+        KvBindingAssets {
+            index: LazyIndex::new(index_bin),
+            kv,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl<'assets> AssetStore for KvBindingAssets<'assets> {
+    fn lookup(&self, path: &str) -> Result<Option<AssetMetadata>, Error> {
+        self.index.lookup(path)
+    }
+
+    async fn get(&self, md: &AssetMetadata) -> Result<Bytes, Error> {
+        self.kv
+            .get(&md.path)
+            .await?
+            .ok_or_else(|| Error::KVKeyNotFound(md.path.clone(), 404))
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<(String, AssetMetadata)>, Error> {
+        self.index.list(prefix)
+    }
+}
+
+/// Assets held in memory, for tests, or for small sites embedded in the worker
+#[derive(Debug, Default)]
+pub struct MemoryAssets {
+    assets: HashMap<String, (AssetMetadata, Bytes)>,
+}
+
+impl MemoryAssets {
+    /// Adds an asset. `modified` is the last-modified time, in seconds since the epoch (UTC).
+    pub fn insert<P: Into<String>, B: Into<Bytes>>(&mut self, path: P, data: B, modified: u64) {
+        let path = path.into();
+        let data = data.into();
+        let md = AssetMetadata {
+            path: path.clone(),
+            modified,
+            size: data.len() as u64,
+        };
+        self.assets.insert(path, (md, data));
+    }
+}
+
+#[async_trait(?Send)]
+impl AssetStore for MemoryAssets {
+    fn lookup(&self, path: &str) -> Result<Option<AssetMetadata>, Error> {
+        Ok(self.assets.get(path).map(|(md, _)| md.clone()))
+    }
+
+    async fn get(&self, md: &AssetMetadata) -> Result<Bytes, Error> {
+        self.assets
+            .get(&md.path)
+            .map(|(_, data)| data.clone())
+            .ok_or_else(|| Error::NoStaticAsset(md.path.clone()))
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<(String, AssetMetadata)>, Error> {
+        Ok(self
+            .assets
+            .iter()
+            .filter(|(path, _)| path.starts_with(prefix))
+            .map(|(path, (md, _))| (path.clone(), md.clone()))
+            .collect())
+    }
+}
+
+/// Assets read from a local directory. This is only available natively (not in wasm),
+/// for testing and local development.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+pub struct DirAssets {
+    root: std::path::PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl DirAssets {
+    /// Serves files from the directory
+    pub fn new<P: Into<std::path::PathBuf>>(root: P) -> Self {
+        DirAssets { root: root.into() }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[async_trait(?Send)]
+impl AssetStore for DirAssets {
+    fn lookup(&self, path: &str) -> Result<Option<AssetMetadata>, Error> {
+        use std::path::Component;
+
+        let rel = std::path::Path::new(path);
+        // don't allow paths outside the root
+        if !rel.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Ok(None);
+        }
+        match std::fs::metadata(self.root.join(rel)) {
+            Ok(meta) if meta.is_file() => Ok(Some(AssetMetadata {
+                path: path.to_string(),
+                modified: meta
+                    .modified()
+                    .ok()
+                    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default(),
+                size: meta.len(),
+            })),
+            _ => Ok(None),
+        }
+    }
+
+    async fn get(&self, md: &AssetMetadata) -> Result<Bytes, Error> {
+        std::fs::read(self.root.join(&md.path))
+            .map(Bytes::from)
+            .map_err(|e| Error::Other(format!("reading {}: {}", md.path, e)))
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<(String, AssetMetadata)>, Error> {
+        // walk the folder containing the prefix
+        let dir = match prefix.rfind('/') {
+            Some(pos) => &prefix[..=pos],
+            None => "",
+        };
+        let mut found = Vec::new();
+        let mut pending = vec![dir.to_string()];
+        while let Some(dir) = pending.pop() {
+            let entries = match std::fs::read_dir(self.root.join(&dir)) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                let path = format!("{}{}", dir, name);
+                match entry.file_type() {
+                    Ok(ft) if ft.is_dir() => pending.push(format!("{}/", path)),
+                    Ok(ft) if ft.is_file() && path.starts_with(prefix) => {
+                        if let Some(md) = self.lookup(&path)? {
+                            found.push((path, md));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(found)
+    }
+}
+
+#[test]
+fn lazy_index() {
+    let md = AssetMetadata {
+        path: "a/b.txt".to_string(),
+        modified: 10000,
+        size: 10,
+    };
+    let mut index = AssetIndex::new();
+    index.insert("a/b.txt".to_string(), md.clone());
+    let blob = bincode::serialize(&index).unwrap();
+
+    let lazy = LazyIndex::new(&blob);
+    assert_eq!(lazy.lookup("a/b.txt").unwrap(), Some(md));
+    assert_eq!(lazy.lookup("xyz").unwrap(), None);
+
+    assert!(matches!(
+        LazyIndex::new(b"\xff").lookup("x"),
+        Err(Error::DeserializeAssets(_))
+    ));
+}
+
+#[test]
+fn key_hashes() {
+    let hex = "73cb3858a687a8494ca3323053016282f3dad39d42cf62ca4e79dda2aac7d9ac";
+    let hash = key_sha256(&format!("js/a.{}.js", hex)).expect("hash");
+    assert_eq!(hash[0], 0x73);
+    assert_eq!(hash[31], 0xac);
+    assert!(key_sha256(&format!("LICENSE.{}", hex)).is_some());
+    assert!(key_sha256(&format!("{}.js", hex)).is_none());
+    assert!(key_sha256("js/a.1234.js").is_none());
+    assert!(key_sha256(&format!("js/a.{}.js", &hex.replace('7', "x"))).is_none());
+}
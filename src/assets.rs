@@ -1,17 +1,142 @@
-use crate::{handler_return, Context, Error, Handler, HandlerReturn, HttpDate, Method, Request};
+use crate::accept_encoding::accepted_encodings;
+use crate::asset_store::key_sha256;
+use crate::glob::glob_match;
+use crate::{
+    check_preconditions, handler_return, AssetCache, AssetStore, CacheControl, Context, Error,
+    Handler, HandlerReturn, HttpDate, KvRestAssets, Method, Request,
+};
 use async_trait::async_trait;
+use bytes::Bytes;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::BTreeSet;
 //use service_logging::{log, Severity};
-use std::str::FromStr;
+use std::rc::Rc;
 
-use kv_assets::{AssetMetadata, KVAssets};
+use kv_assets::AssetMetadata;
 
-/// Serves static assets out of Worker KV storage.
+/// Serves static assets out of Worker KV storage, or another [`AssetStore`].
+///
+/// Caching rules set the Cache-Control header according to the asset path or media type.
+/// Rules are checked in the order they were added, and the first match is used:
+///```rust,ignore
+/// let store = KvBindingAssets::init(INDEX, env.kv("ASSETS")?);
+/// let handler = StaticAssetHandler::new(store)
+///     .with_path_cache("/assets/*.js", CacheControl::new().public()
+///         .max_age(Duration::from_secs(31536000)).immutable())
+///     .with_type_cache("image/*", CacheControl::new().public().max_age(Duration::from_secs(86400)))
+///     .with_path_cache("*.html", CacheControl::new().no_cache());
+///```
+///
+/// For single-page apps, unknown paths requested by browsers (with `Accept: text/html`)
+/// can be answered with the app's index page, and other unknown paths with a custom 404 page.
+/// Paths with an excluded prefix are left for other handlers:
+///```rust,ignore
+/// let handler = StaticAssetHandler::new(store)
+///     .with_spa_fallback("/index.html")
+///     .with_not_found("/404.html")
+///     .with_fallback_exclude("/api/");
+///```
+///
+/// Url paths ending in '/' are served from the folder's index file ("index.html" by default).
+/// Assets may be mounted under a url prefix, and pages may be served without
+/// the ".html" extension:
+///```rust,ignore
+/// // "/static/about" is served from "about.html", "/static/docs/" from "docs/index.html",
+/// // and "/static/docs" is redirected to "/static/docs/"
+/// let handler = StaticAssetHandler::new(store)
+///     .with_prefix("/static")
+///     .with_clean_urls(true)
+///     .with_trailing_slash_redirect(true);
+///```
+///
+/// Folders without an index file can be listed, if they are under an allowed prefix:
+///```rust,ignore
+/// let handler = StaticAssetHandler::new(store).with_listing("/artifacts/");
+///```
 pub struct StaticAssetHandler<'assets> {
-    kv: KVAssets<'assets>,
+    store: Box<dyn AssetStore + 'assets>,
+    cache_rules: Vec<(CacheMatch, CacheControl)>,
+    spa_fallback: Option<String>,
+    not_found: Option<String>,
+    fallback_exclude: Vec<String>,
+    index_files: Vec<String>,
+    clean_urls: bool,
+    trailing_slash_redirect: bool,
+    prefix: String,
+    cache: Option<Rc<AssetCache>>,
+    listing_prefixes: Vec<String>,
+    digest: bool,
+}
+
+/// Asset selected for a response
+struct Served {
+    /// path of the asset in the store
+    path: String,
+    /// metadata of the asset
+    md: AssetMetadata,
+    /// metadata of the content to send: the asset, or its pre-compressed variant
+    content: AssetMetadata,
+    /// content-encoding of the pre-compressed variant
+    encoding: Option<&'static str>,
+}
+
+/// Result of mapping a url path to the asset store
+#[derive(Debug, PartialEq)]
+enum Resolved {
+    /// path of the asset in the store
+    Asset(String),
+    /// path is a folder without a trailing slash: redirect to the url with a slash added
+    Redirect,
+}
+
+/// Selects assets for a caching rule
+#[derive(Debug)]
+enum CacheMatch {
+    /// glob pattern for the asset path
+    Path(String),
+    /// media type, or type with wildcard subtype (e.g. "image/*")
+    MediaType(String),
+}
+
+impl CacheMatch {
+    fn matches(&self, path: &str, mtype: Option<&str>) -> bool {
+        match self {
+            CacheMatch::Path(glob) => glob_match(glob, path),
+            CacheMatch::MediaType(rule) => {
+                let essence = match mtype {
+                    Some(mtype) => mtype.split(';').next().unwrap_or_default().trim(),
+                    None => return false,
+                };
+                match rule.strip_suffix("/*") {
+                    Some(top) => essence
+                        .split('/')
+                        .next()
+                        .map(|t| t.eq_ignore_ascii_case(top))
+                        .unwrap_or(false),
+                    None => essence.eq_ignore_ascii_case(rule),
+                }
+            }
+        }
+    }
+}
+
+/// Returns the policy of the first rule matching the path
+fn find_cache_rule<'r>(
+    rules: &'r [(CacheMatch, CacheControl)],
+    path: &str,
+) -> Option<&'r CacheControl> {
+    let mtype = crate::media_type(path);
+    rules
+        .iter()
+        .find(|(rule, _)| rule.matches(path, mtype))
+        .map(|(_, policy)| policy)
 }
 
 impl<'assets> StaticAssetHandler<'assets> {
-    /// Initialize static asset handler
+    /// Initialize static asset handler, reading assets from KV with the Cloudflare REST api
+    /// (see [`KvRestAssets`]).
     /// `index_bin` is the serialized AssetIndex, which will be deserialized lazily (if needed)
     /// `account_id` is Cloudflare account id
     /// `namespace_id` is cloudflare KV namespace id (the long hex string, not the friendly name)
@@ -22,11 +147,219 @@ impl<'assets> StaticAssetHandler<'assets> {
         namespace_id: &'_ str,
         auth_token: &'_ str,
     ) -> Self {
+        Self::new(KvRestAssets::init(
+            index_bin,
+            account_id,
+            namespace_id,
+            auth_token,
+        ))
+    }
+
+    /// Initialize static asset handler with the asset store
+    pub fn new<S: AssetStore + 'assets>(store: S) -> Self {
         Self {
-            kv: KVAssets::init(index_bin, account_id, namespace_id, auth_token),
+            store: Box::new(store),
+            cache_rules: Vec::new(),
+            spa_fallback: None,
+            not_found: None,
+            fallback_exclude: Vec::new(),
+            index_files: vec!["index.html".to_string()],
+            clean_urls: false,
+            trailing_slash_redirect: false,
+            prefix: String::new(),
+            cache: None,
+            listing_prefixes: Vec::new(),
+            digest: false,
+        }
+    }
+
+    /// Adds a caching rule for assets whose path matches the glob pattern.
+    /// In the pattern, `*` matches within one path segment, and `**` matches any number
+    /// of segments. A pattern without a `/` (such as `*.js`) matches the file name
+    /// in any folder; use `**` to match all assets.
+    pub fn with_path_cache(mut self, glob: &str, policy: CacheControl) -> Self {
+        self.cache_rules
+            .push((CacheMatch::Path(glob.to_string()), policy));
+        self
+    }
+
+    /// Adds a caching rule for assets with the media type (as determined by
+    /// [`media_type`](crate::media_type)), such as `text/html`, or `image/*` for all images.
+    pub fn with_type_cache(mut self, media_type: &str, policy: CacheControl) -> Self {
+        self.cache_rules
+            .push((CacheMatch::MediaType(media_type.to_string()), policy));
+        self
+    }
+
+    /// Serves the asset at `path` (such as "/index.html") for GET or HEAD requests that
+    /// accept `text/html` and don't match any asset, so that a single-page app can do
+    /// its own routing. Requests with a prefix added by
+    /// [`with_fallback_exclude`](StaticAssetHandler::with_fallback_exclude) are not affected.
+    pub fn with_spa_fallback(mut self, path: &str) -> Self {
+        self.spa_fallback = Some(remove_leading_slash(path).to_string());
+        self
+    }
+
+    /// Serves the asset at `path` (such as "/404.html"), with status 404, for GET or HEAD
+    /// requests that don't match any asset (or the spa fallback). Since the response is set,
+    /// handlers after this one will not run for those requests, so the StaticAssetHandler
+    /// should be last, or other handlers' paths should be excluded with
+    /// [`with_fallback_exclude`](StaticAssetHandler::with_fallback_exclude).
+    pub fn with_not_found(mut self, path: &str) -> Self {
+        self.not_found = Some(remove_leading_slash(path).to_string());
+        self
+    }
+
+    /// Excludes url paths starting with the prefix (such as "/api/") from the spa fallback
+    /// and custom 404 page, so they fall through to other handlers. May be called more than once.
+    pub fn with_fallback_exclude(mut self, prefix: &str) -> Self {
+        self.fallback_exclude.push(prefix.to_string());
+        self
+    }
+
+    /// Sets the file names that are tried, in order, for url paths ending in '/'.
+    /// The default is "index.html".
+    pub fn with_index_files(mut self, names: &[&str]) -> Self {
+        self.index_files = names.iter().map(|name| name.to_string()).collect();
+        self
+    }
+
+    /// If enabled, url paths without an extension that don't match an asset
+    /// are served from the path with ".html" added, so "/about" is served from "about.html".
+    /// The default is false.
+    pub fn with_clean_urls(mut self, enable: bool) -> Self {
+        self.clean_urls = enable;
+        self
+    }
+
+    /// If enabled, requests for a folder without the trailing slash (such as "/docs"),
+    /// where the folder has an index file, are redirected (301) to the url with the slash
+    /// ("/docs/"), so that relative links in the index page resolve correctly.
+    /// The default is false.
+    pub fn with_trailing_slash_redirect(mut self, enable: bool) -> Self {
+        self.trailing_slash_redirect = enable;
+        self
+    }
+
+    /// Serves assets under the url prefix (such as "/static"), which is removed from
+    /// the url path to get the asset path. Requests outside the prefix are not handled.
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.trim_end_matches('/').to_string();
+        if !self.prefix.is_empty() && !self.prefix.starts_with('/') {
+            self.prefix.insert(0, '/');
+        }
+        self
+    }
+
+    /// Keeps asset content in the in-memory cache, so that repeated requests
+    /// for the same asset don't need to read it from the store
+    pub fn with_cache(mut self, cache: Rc<AssetCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Enables listings of folders under the asset path prefix (such as "/artifacts/")
+    /// that have no index file. The listing is HTML, or JSON if the request accepts
+    /// `application/json`, and shows the name, size, modification time, and media type
+    /// of each asset. May be called more than once; use "/" to allow listing all folders.
+    /// Listings require an asset store that implements [`AssetStore::list`].
+    pub fn with_listing(mut self, prefix: &str) -> Self {
+        let mut prefix = remove_leading_slash(prefix).to_string();
+        if !prefix.is_empty() && !prefix.ends_with('/') {
+            prefix.push('/');
+        }
+        self.listing_prefixes.push(prefix);
+        self
+    }
+
+    /// If enabled, responses include `Digest` and `Repr-Digest` headers with the sha256 hash
+    /// of the content, for assets whose KV key contains the hash (as generated by
+    /// [`IndexBuilder`](crate::IndexBuilder)). The default is false.
+    /// The ETag of these assets is always set from the hash.
+    pub fn with_digest(mut self, enable: bool) -> Self {
+        self.digest = enable;
+        self
+    }
+
+    /// Returns the subresource integrity value (such as "sha256-...") of the asset at the path,
+    /// for the `integrity` attribute of script and link elements. Returns None if there is
+    /// no asset at the path, or its hash is not known (see [`with_digest`](Self::with_digest)).
+    pub fn integrity(&self, path: &str) -> Option<String> {
+        match self.store.lookup(remove_leading_slash(path)) {
+            Ok(Some(md)) => {
+                key_sha256(&md.path).map(|hash| format!("sha256-{}", base64::encode(hash)))
+            }
+            _ => None,
         }
     }
 
+    /// Sets ETag, and optionally digest headers, from the content hash in the asset's key.
+    /// Returns the ETag, or None if the key does not contain the hash.
+    fn set_hash_headers(&self, md: &AssetMetadata, ctx: &mut Context) -> Option<String> {
+        let hash = key_sha256(&md.path)?;
+        let etag = format!("\"{}\"", hex::encode(hash));
+        // unwraps ok because header values are ascii
+        ctx.response().header("etag", &etag).unwrap();
+        if self.digest {
+            let b64 = base64::encode(hash);
+            ctx.response()
+                .header("digest", format!("sha-256={}", b64))
+                .unwrap()
+                .header("repr-digest", format!("sha-256=:{}:", b64))
+                .unwrap();
+        }
+        Some(etag)
+    }
+
+    /// Returns the url path with the prefix removed, or None if the path is outside the prefix.
+    /// The result is empty or starts with '/'.
+    fn strip_prefix<'p>(&self, url_path: &'p str) -> Option<&'p str> {
+        url_path
+            .strip_prefix(self.prefix.as_str())
+            .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+
+    /// Maps the url path (with prefix removed) to an asset in the store, trying index files
+    /// for folders, ".html" for clean urls, and folder redirects, if enabled.
+    /// Returns None if there is no matching asset.
+    fn resolve(&self, rest: &str) -> Option<Resolved> {
+        let exists = |path: &str| matches!(self.store.lookup(path), Ok(Some(_)));
+        let path = remove_leading_slash(rest);
+        let in_folder = |name: &str| {
+            if path.is_empty() || path.ends_with('/') {
+                format!("{}{}", path, name)
+            } else {
+                format!("{}/{}", path, name)
+            }
+        };
+        if rest.ends_with('/') {
+            return self
+                .index_files
+                .iter()
+                .map(|name| in_folder(name))
+                .find(|index| exists(index))
+                .map(Resolved::Asset);
+        }
+        if exists(path) {
+            return Some(Resolved::Asset(path.to_string()));
+        }
+        if self.clean_urls
+            && !path.is_empty()
+            && !path.rsplit('/').next().unwrap_or(path).contains('.')
+        {
+            let html = format!("{}.html", path);
+            if exists(&html) {
+                return Some(Resolved::Asset(html));
+            }
+        }
+        if self.trailing_slash_redirect
+            && self.index_files.iter().any(|name| exists(&in_folder(name)))
+        {
+            return Some(Resolved::Redirect);
+        }
+        None
+    }
+
     /// Returns true if there is a static asset matching this path.
     /// Only checks the manifest - does not check KV. This could give a false positive
     /// positive if the manifest is out of date, so site developers must ensure that
@@ -43,23 +376,165 @@ impl<'assets> StaticAssetHandler<'assets> {
     /// and reliable networking, this should be accurate.
     pub fn has_asset(&self, req: &Request) -> bool {
         (req.method() == Method::GET || req.method() == Method::HEAD)
-            && matches!(self.kv.lookup_key(req.url().path()), Ok(Some(_)))
+            && self
+                .strip_prefix(req.url().path())
+                .and_then(|rest| self.resolve(rest))
+                .is_some()
+    }
+
+    /// Sets the response to a listing of the folder (an asset path ending in '/', or empty
+    /// for the root), if listings are enabled for it and it contains any assets.
+    /// Returns true if the response was set.
+    fn list_folder(
+        &self,
+        folder: &str,
+        req: &Request,
+        ctx: &mut Context,
+    ) -> Result<bool, HandlerReturn> {
+        if !self
+            .listing_prefixes
+            .iter()
+            .any(|prefix| folder.starts_with(prefix.as_str()))
+        {
+            return Ok(false);
+        }
+        let entries = match self.store.list(folder) {
+            Ok(assets) => folder_entries(folder, assets),
+            Err(e) => {
+                ctx.raise_internal_error(Box::new(e));
+                return Err(handler_return(200, "")); // handle internal error higher in the stack
+            }
+        };
+        if entries.is_empty() {
+            return Ok(false);
+        }
+        if accepts(req, "application/json") {
+            ctx.response().json(&entries)?;
+        } else {
+            ctx.response()
+                .content_type(mime::TEXT_HTML_UTF_8)?
+                .text(listing_html(req.url().path(), &entries));
+        }
+        Ok(true)
+    }
+
+    /// Reads the asset content from the cache, if enabled, or from the store
+    async fn read(&self, md: &AssetMetadata) -> Result<Bytes, Error> {
+        if let Some(cache) = &self.cache {
+            if let Some(data) = cache.get(md) {
+                return Ok(data);
+            }
+        }
+        let data = self.store.get(md).await?;
+        if let Some(cache) = &self.cache {
+            cache.insert(md, data.clone());
+        }
+        Ok(data)
+    }
+
+    /// Finds the asset to serve for a request that didn't match an asset:
+    /// the spa fallback, with status 200, or the custom 404 page, with status 404.
+    /// Returns the asset's path, metadata, and status, or Ok(None) if there is no fallback.
+    fn find_fallback(
+        &self,
+        req: &Request,
+        ctx: &mut Context,
+    ) -> Result<Option<(Served, u16)>, HandlerReturn> {
+        let url_path = req.url().path();
+        if self
+            .fallback_exclude
+            .iter()
+            .any(|prefix| url_path.starts_with(prefix.as_str()))
+        {
+            return Ok(None);
+        }
+        if let Some(spa) = &self.spa_fallback {
+            if accepts(req, "text/html") {
+                if let Some(served) = self.check_metadata(spa, req, ctx)? {
+                    return Ok(Some((served, 200)));
+                }
+            }
+        }
+        if let Some(not_found) = &self.not_found {
+            // not using check_metadata, because conditional requests and cache rules
+            // don't apply to 404 responses
+            match self.store.lookup(not_found) {
+                Ok(Some(md)) => {
+                    return Ok(Some((self.select_variant(not_found, md, req, ctx), 404)));
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    // a store error is an internal error, not a missing page
+                    ctx.raise_internal_error(Box::new(e));
+                    return Err(handler_return(200, "")); // handle internal error higher in the stack
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Looks for pre-compressed variants of the asset (`path.br`, `path.gz`) in the store,
+    /// and selects the one most preferred by the client, if any.
+    /// If there are any variants, `Vary: Accept-Encoding` is added to the response.
+    /// The ETag and digest headers are set for the selected content.
+    fn select_variant(
+        &self,
+        path: &str,
+        md: AssetMetadata,
+        req: &Request,
+        ctx: &mut Context,
+    ) -> Served {
+        let variants = PRECOMPRESSED
+            .iter()
+            .filter_map(
+                |(ext, _)| match self.store.lookup(&format!("{}.{}", path, ext)) {
+                    Ok(Some(md)) => Some((*ext, md)),
+                    _ => None,
+                },
+            )
+            .collect::<Vec<_>>();
+        let mut served = Served {
+            path: path.to_string(),
+            content: md.clone(),
+            md,
+            encoding: None,
+        };
+        if !variants.is_empty() {
+            // unwrap ok because header value is ascii
+            ctx.response()
+                .append_header("vary", "Accept-Encoding")
+                .unwrap();
+            let accept = req.get_header("accept-encoding").unwrap_or_default();
+            if let Some((variant, encoding)) =
+                accepted_variants(&accept)
+                    .into_iter()
+                    .find_map(|(ext, encoding)| {
+                        variants
+                            .iter()
+                            .find(|(e, _)| *e == ext)
+                            .map(|(_, variant)| (variant.clone(), encoding))
+                    })
+            {
+                served.content = variant;
+                served.encoding = Some(encoding);
+            }
+        }
+        served
     }
 
-    /// Does some quick checks and may return
-    /// - 304 Not Modified, if request had if-modified-since header and doc was <= header date
-    /// - 200 if request was HEAD method
+    /// Looks up the asset, sets its cache policy, and selects the content to send.
+    /// Returns Err with 304 Not Modified if the request's `If-None-Match` matches the ETag,
+    /// or if it has no `If-None-Match`, and `If-Modified-Since` is not earlier than the
+    /// asset's modification time. The 304 response has the same Cache-Control, ETag,
+    /// and Vary headers as the full response.
     /// Returns Ok(None) if content is not found (no path match)
-    /// Returns Ok(Some(metadata)) if doc is found
     fn check_metadata(
         &self,
         path: &str,
         req: &Request,
         ctx: &mut Context,
-    ) -> Result<Option<AssetMetadata>, HandlerReturn> {
-        use reqwest::header::IF_MODIFIED_SINCE;
-
-        match self.kv.lookup_key(path) {
+    ) -> Result<Option<Served>, HandlerReturn> {
+        match self.store.lookup(path) {
             Err(e) => {
                 ctx.raise_internal_error(Box::new(e));
                 Err(handler_return(200, "")) // handle internal error higher in the stack
@@ -69,35 +544,158 @@ impl<'assets> StaticAssetHandler<'assets> {
                 Ok(None)
             }
             Ok(Some(md)) => {
-                // GET or HEAD
-                if let Some(dt) = req.get_header(IF_MODIFIED_SINCE.as_str()) {
-                    if let Ok(http_date) = HttpDate::from_str(dt.as_str()) {
-                        // valid if-modified-since header with parsable date
-                        // if kv is same or older (smaller time), return Not Modified
-                        if md.modified <= http_date.timestamp() as u64 {
-                            return Err(handler_return(304, "Not Modified"));
-                        }
-                        // else modified, so fall through
-                    } else {
-                        // don't bother logging date parse errors
-                        //log!(ctx, Severity::Warning, _:"parse_date_err", val:&dt)
-                    }
-                }
-                /*
-                // HEAD only
-                if req.method() == Method::HEAD {
-                    ctx.response()
-                        .header(LAST_MODIFIED, HttpDate::from(md.modified).to_string())
-                        .unwrap(); // unwrap is ok because number.to_string() is always ascii
-                    Err(handler_return(200, ""))
-                } else {
-                    Ok(md)
+                // set caching policy, which also applies to 304 responses
+                if let Some(policy) = find_cache_rule(&self.cache_rules, path) {
+                    ctx.response().cache_control(policy);
                 }
-                */
-                Ok(Some(md))
+                let served = self.select_variant(path, md, req, ctx);
+                let etag = self.set_hash_headers(&served.content, ctx);
+                check_preconditions(
+                    req,
+                    etag.as_deref(),
+                    Some(HttpDate::from(served.content.modified)),
+                )?;
+                Ok(Some(served))
+            }
+        }
+    }
+}
+
+/// File extensions of pre-compressed asset variants, with their content-encoding
+const PRECOMPRESSED: [(&str, &str); 2] = [("br", "br"), ("gz", "gzip")];
+
+/// Returns the pre-compressed variants (extension, content-encoding) acceptable
+/// to the client, according to the Accept-Encoding header, most preferred first.
+/// Brotli is preferred over gzip if the client has no preference.
+fn accepted_variants(accept: &str) -> Vec<(&'static str, &'static str)> {
+    let encodings = PRECOMPRESSED
+        .iter()
+        .map(|(_, enc)| *enc)
+        .collect::<Vec<&'static str>>();
+    accepted_encodings(accept, &encodings)
+        .into_iter()
+        .filter_map(|enc| PRECOMPRESSED.iter().find(|(_, e)| *e == enc).copied())
+        .collect()
+}
+
+/// Returns true if the request's Accept header includes the media type.
+/// Browsers include text/html for page navigation.
+fn accepts(req: &Request, mtype: &str) -> bool {
+    req.get_header("accept")
+        .map(|accept| {
+            accept.split(',').any(|item| {
+                item.split(';')
+                    .next()
+                    .unwrap_or_default()
+                    .trim()
+                    .eq_ignore_ascii_case(mtype)
+            })
+        })
+        .unwrap_or(false)
+}
+
+/// File or subfolder in a folder listing
+#[derive(Debug, PartialEq, Serialize)]
+struct ListingEntry {
+    /// file name, or folder name ending in '/'
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    modified: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    media_type: Option<&'static str>,
+}
+
+/// Returns the subfolders, then the files, directly in the folder, sorted by name
+fn folder_entries(folder: &str, assets: Vec<(String, AssetMetadata)>) -> Vec<ListingEntry> {
+    let mut folders = BTreeSet::new();
+    let mut files = Vec::new();
+    for (path, md) in assets {
+        let name = match path.strip_prefix(folder) {
+            Some(name) if !name.is_empty() => name,
+            _ => continue,
+        };
+        match name.find('/') {
+            Some(pos) => {
+                folders.insert(name[..=pos].to_string());
             }
+            None => files.push(ListingEntry {
+                name: name.to_string(),
+                size: Some(md.size),
+                modified: Some(HttpDate::from(md.modified).to_string()),
+                media_type: crate::media_type(name),
+            }),
         }
     }
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+    folders
+        .into_iter()
+        .map(|name| ListingEntry {
+            name,
+            size: None,
+            modified: None,
+            media_type: None,
+        })
+        .chain(files)
+        .collect()
+}
+
+/// Renders folder listing as an html page
+fn listing_html(url_path: &str, entries: &[ListingEntry]) -> String {
+    let title = format!("Index of {}", escape_html(url_path));
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{0}</title></head>\n\
+         <body><h1>{0}</h1>\n<table>\n\
+         <tr><th>Name</th><th>Size</th><th>Modified</th><th>Type</th></tr>\n",
+        title
+    );
+    if url_path != "/" {
+        html.push_str("<tr><td><a href=\"../\">../</a></td><td></td><td></td><td></td></tr>\n");
+    }
+    for entry in entries {
+        // folder names end with '/', which is kept in the link
+        let href = match entry.name.strip_suffix('/') {
+            Some(folder) => format!("{}/", encode_path_segment(folder)),
+            None => encode_path_segment(&entry.name),
+        };
+        html.push_str(&format!(
+            "<tr><td><a href=\"{}\">{}</a></td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            href,
+            escape_html(&entry.name),
+            entry.size.map(|size| size.to_string()).unwrap_or_default(),
+            entry.modified.as_deref().unwrap_or_default(),
+            entry.media_type.unwrap_or_default(),
+        ));
+    }
+    html.push_str("</table>\n</body></html>\n");
+    html
+}
+
+/// Characters that are percent-encoded in path segments: all except unreserved
+/// characters (RFC 3986), so the result is also safe in html attributes
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Percent-encodes a url path segment
+fn encode_path_segment(s: &str) -> String {
+    utf8_percent_encode(s, PATH_SEGMENT).to_string()
+}
+
+/// Decodes a percent-encoded url path. Invalid utf-8 sequences are replaced.
+fn decode_path(path: &str) -> Cow<'_, str> {
+    percent_decode_str(path).decode_utf8_lossy()
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
 }
 
 fn remove_leading_slash(path: &str) -> &str {
@@ -106,39 +704,82 @@ fn remove_leading_slash(path: &str) -> &str {
 
 #[async_trait(?Send)]
 impl<'assets> Handler for StaticAssetHandler<'assets> {
-    /// Process incoming Request. If no asset was found at the request path, response.is_unset() will be true.
-    /// Only handles GET and HEAD requests.
+    /// Process incoming Request. If no asset was found at the request path, and there is no
+    /// spa fallback or custom 404 page for it, response.is_unset() will be true.
+    /// Only handles GET and HEAD requests. For HEAD requests, the value is not read from KV.
     async fn handle(&self, req: &Request, mut ctx: &mut Context) -> Result<(), HandlerReturn> {
-        let path = remove_leading_slash(req.url().path());
-        if (req.method() != Method::GET && req.method() != Method::HEAD) || path.is_empty() {
+        if req.method() != Method::GET && req.method() != Method::HEAD {
             return Ok(());
         }
-        // change trailing slash, indicating 'folder' to folder/index.html
-        let path = if path.ends_with('/') {
-            format!("{}/index.html", path)
-        } else {
-            path.to_string()
+        let rest = match self.strip_prefix(req.url().path()) {
+            Some(rest) => rest,
+            None => return Ok(()),
+        };
+        // asset paths are not encoded, so "my%20file.txt" is served from "my file.txt"
+        let rest = decode_path(rest);
+        let rest = rest.as_ref();
+        let path = match self.resolve(rest) {
+            Some(Resolved::Asset(path)) => path,
+            Some(Resolved::Redirect) => {
+                let mut url = req.url().clone();
+                url.set_path(&format!("{}/", url.path()));
+                ctx.response()
+                    .header(reqwest::header::LOCATION, url.as_str())
+                    .unwrap() // unwrap ok because url is ascii
+                    .status(301);
+                return Ok(());
+            }
+            None => {
+                let path = remove_leading_slash(rest);
+                if rest.ends_with('/') && self.list_folder(path, req, ctx)? {
+                    return Ok(());
+                }
+                // not found, but check_metadata reports any store errors
+                path.to_string()
+            }
         };
         // This may return quickly if response can be satisfied without querying KV,
-        // such as HEAD requests, or If-modified-since header when it hasn't been modified
-        let md = match self.check_metadata(&path, req, &mut ctx)? {
-            None => return Ok(()), // not found: fall through to let service handler deal with it
-            Some(md) => md,
+        // such as If-modified-since header when it hasn't been modified
+        let (served, status) = match self.check_metadata(&path, req, &mut ctx)? {
+            Some(served) => (served, 200),
+            None => match self.find_fallback(req, ctx)? {
+                Some(fallback) => fallback,
+                None => return Ok(()), // not found: fall through to let service handler deal with it
+            },
         };
+        if status != 200 {
+            self.set_hash_headers(&served.content, ctx);
+        }
+        if let Some(encoding) = served.encoding {
+            // unwrap ok because header value is ascii
+            ctx.response().header("content-encoding", encoding).unwrap();
+        }
+        // content type is from the original path, even if a compressed variant is served
+        let mtype = crate::media_type(&served.md.path);
+        let (path, md) = (served.path, served.content);
+        // if we can figure out the content type, report it
+        // otherwise let browser sniff it
+        if let Some(mt) = mtype {
+            ctx.response()
+                .header(reqwest::header::CONTENT_TYPE, mt.to_string())
+                .unwrap();
+        }
+        ctx.response()
+            .header("last-modified", HttpDate::from(md.modified).to_string())
+            .unwrap();
+        if req.is_head() {
+            // HEAD only: headers are available from metadata, so skip reading the value from KV
+            ctx.response()
+                .header(reqwest::header::CONTENT_LENGTH, md.size.to_string())
+                .unwrap() // unwrap is ok because number.to_string() is always ascii
+                .status(status)
+                .range(req);
+            return Ok(());
+        }
         // have metadata, asset is in KV (unless manifest is out of date)
-        match self.kv.get_kv_value(&md.path).await {
+        match self.read(&md).await {
             Ok(bytes) => {
-                // if we can figure out the content type, report it
-                // otherwise let browser sniff it
-                if let Some(mt) = crate::media_type(&md.path) {
-                    ctx.response()
-                        .header(reqwest::header::CONTENT_TYPE, mt.to_string())
-                        .unwrap();
-                }
-                ctx.response()
-                    .header("last-modified", HttpDate::from(md.modified).to_string())
-                    .unwrap()
-                    .body(bytes.to_vec());
+                ctx.response().status(status).body(bytes).range(req);
             }
             Err(e) => {
                 ctx.raise_internal_error(Box::new(Error::Other(format!(
@@ -152,3 +793,321 @@ impl<'assets> Handler for StaticAssetHandler<'assets> {
         Ok(())
     }
 }
+
+#[test]
+fn cache_rules() {
+    use std::time::Duration;
+
+    let immutable = CacheControl::new()
+        .public()
+        .max_age(Duration::from_secs(31536000))
+        .immutable();
+    let rules = vec![
+        (
+            CacheMatch::Path("/assets/**".to_string()),
+            immutable.clone(),
+        ),
+        (
+            CacheMatch::MediaType("image/*".to_string()),
+            CacheControl::new().max_age(Duration::from_secs(60)),
+        ),
+        (
+            CacheMatch::MediaType("text/html".to_string()),
+            CacheControl::new().no_cache(),
+        ),
+    ];
+    assert_eq!(
+        find_cache_rule(&rules, "assets/img/logo.png"),
+        Some(&immutable)
+    );
+    assert_eq!(
+        find_cache_rule(&rules, "img/logo.png").map(|cc| cc.to_string()),
+        Some("max-age=60".to_string())
+    );
+    assert_eq!(
+        find_cache_rule(&rules, "index.html").map(|cc| cc.to_string()),
+        Some("no-cache".to_string())
+    );
+    assert_eq!(find_cache_rule(&rules, "data.json"), None);
+}
+
+#[test]
+fn precompressed_variants() {
+    assert_eq!(
+        accepted_variants("gzip, deflate, br"),
+        vec![("br", "br"), ("gz", "gzip")]
+    );
+    assert_eq!(accepted_variants("gzip"), vec![("gz", "gzip")]);
+    assert_eq!(accepted_variants("x-gzip"), vec![("gz", "gzip")]);
+    assert_eq!(
+        accepted_variants("br;q=0.5, gzip"),
+        vec![("gz", "gzip"), ("br", "br")]
+    );
+    assert_eq!(accepted_variants("br;q=0, *"), vec![("gz", "gzip")]);
+    assert_eq!(accepted_variants("identity"), vec![]);
+    assert_eq!(accepted_variants(""), vec![]);
+}
+
+#[test]
+fn resolve_paths() {
+    let mut store = crate::MemoryAssets::default();
+    for path in &[
+        "index.html",
+        "about.html",
+        "docs/index.html",
+        "app.js",
+        "x/home.htm",
+    ] {
+        store.insert(*path, "", 0);
+    }
+    let handler = StaticAssetHandler::new(store);
+    let asset = |path: &str| Some(Resolved::Asset(path.to_string()));
+
+    assert_eq!(handler.resolve("/"), asset("index.html"));
+    assert_eq!(handler.resolve("/docs/"), asset("docs/index.html"));
+    assert_eq!(handler.resolve("/app.js"), asset("app.js"));
+    assert_eq!(handler.resolve("/about"), None);
+    assert_eq!(handler.resolve("/docs"), None);
+    assert_eq!(handler.resolve("/x/"), None);
+
+    let handler = handler
+        .with_clean_urls(true)
+        .with_trailing_slash_redirect(true)
+        .with_index_files(&["index.html", "home.htm"])
+        .with_prefix("static/");
+    assert_eq!(handler.resolve("/about"), asset("about.html"));
+    assert_eq!(handler.resolve("/app"), None);
+    assert_eq!(handler.resolve("/docs"), Some(Resolved::Redirect));
+    assert_eq!(handler.resolve(""), Some(Resolved::Redirect));
+    assert_eq!(handler.resolve("/x/"), asset("x/home.htm"));
+
+    assert_eq!(handler.strip_prefix("/static/app.js"), Some("/app.js"));
+    assert_eq!(handler.strip_prefix("/static"), Some(""));
+    assert_eq!(handler.strip_prefix("/staticfiles/app.js"), None);
+    assert_eq!(handler.strip_prefix("/app.js"), None);
+}
+
+#[test]
+fn folder_listing() {
+    let md = |path: &str, size: u64| AssetMetadata {
+        path: path.to_string(),
+        modified: 1_600_000_000,
+        size,
+    };
+    let assets = vec![
+        ("builds/v2/app.wasm".to_string(), md("k1", 10)),
+        ("builds/notes.txt".to_string(), md("k2", 5)),
+        ("builds/v1/app.wasm".to_string(), md("k3", 20)),
+        ("builds/v1/old/a.txt".to_string(), md("k4", 1)),
+        ("builds/<b>.html".to_string(), md("k5", 2)),
+        ("builds/a b#c?100%.txt".to_string(), md("k6", 3)),
+    ];
+    let entries = folder_entries("builds/", assets);
+    let names = entries.iter().map(|e| e.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        vec!["v1/", "v2/", "<b>.html", "a b#c?100%.txt", "notes.txt"]
+    );
+    assert_eq!(entries[0].size, None);
+    assert_eq!(entries[4].size, Some(5));
+    assert_eq!(entries[4].media_type, Some("text/plain"));
+    assert_eq!(
+        entries[4].modified.as_deref(),
+        Some(HttpDate::from(1_600_000_000u64).to_string().as_str())
+    );
+
+    let html = listing_html("/builds/", &entries);
+    assert!(html.contains("<title>Index of /builds/</title>"));
+    assert!(html.contains("<a href=\"../\">"));
+    assert!(html.contains("<a href=\"%3Cb%3E.html\">&lt;b&gt;.html</a>"));
+    assert!(html.contains("<a href=\"a%20b%23c%3F100%25.txt\">a b#c?100%.txt</a>"));
+    assert!(html.contains("<a href=\"v1/\">v1/</a>"));
+
+    // listed names are found from their links
+    let mut store = crate::MemoryAssets::default();
+    store.insert("builds/my file#1.txt", "", 0);
+    let handler = StaticAssetHandler::new(store);
+    let href = encode_path_segment("my file#1.txt");
+    assert_eq!(href, "my%20file%231.txt");
+    assert_eq!(
+        handler.resolve(&decode_path(&format!("/builds/{}", href))),
+        Some(Resolved::Asset("builds/my file#1.txt".to_string()))
+    );
+}
+
+#[test]
+fn integrity_from_key() {
+    let hex = "73cb3858a687a8494ca3323053016282f3dad39d42cf62ca4e79dda2aac7d9ac";
+    let mut store = crate::MemoryAssets::default();
+    store.insert(format!("js/app.{}.js", hex), "x\n", 0);
+    store.insert("js/other.js", "", 0);
+    let handler = StaticAssetHandler::new(store);
+    assert_eq!(
+        handler.integrity(&format!("/js/app.{}.js", hex)).as_deref(),
+        Some("sha256-c8s4WKaHqElMozIwUwFigvPa051Cz2LKTnndoqrH2aw=")
+    );
+    assert_eq!(handler.integrity("/js/other.js"), None);
+    assert_eq!(handler.integrity("/missing.js"), None);
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod test {
+    use super::*;
+    use crate::{MemoryAssets, Middleware, Url};
+    use wasm_bindgen_test::*;
+
+    const HEX: &str = "73cb3858a687a8494ca3323053016282f3dad39d42cf62ca4e79dda2aac7d9ac";
+
+    fn handler() -> StaticAssetHandler<'static> {
+        let mut store = MemoryAssets::default();
+        store.insert(
+            format!("js/app.{}.js", HEX),
+            "x".repeat(2000),
+            1_600_000_000,
+        );
+        StaticAssetHandler::new(store).with_digest(true)
+    }
+
+    fn request(headers: &[(&str, &str)]) -> Request {
+        let url = format!("https://example.com/js/app.{}.js", HEX);
+        let hdrs = web_sys::Headers::new().unwrap();
+        for (name, value) in headers {
+            hdrs.set(name, value).unwrap();
+        }
+        Request::new(Method::GET, Url::parse(&url).unwrap(), hdrs, None)
+    }
+
+    #[wasm_bindgen_test]
+    async fn conditional_requests() {
+        let handler = handler();
+        let etag = format!("\"{}\"", HEX);
+
+        let mut ctx = Context::default();
+        handler.handle(&request(&[]), &mut ctx).await.unwrap();
+        assert_eq!(ctx.response().get_status(), 200);
+        assert_eq!(ctx.response().get_header("etag"), Some(etag.clone()));
+
+        // If-None-Match is checked, and the 304 response has the ETag
+        let mut ctx = Context::default();
+        let ret = handler
+            .handle(&request(&[("if-none-match", &etag)]), &mut ctx)
+            .await
+            .unwrap_err();
+        assert_eq!(ret.status, 304);
+        assert_eq!(ctx.response().get_header("etag"), Some(etag.clone()));
+
+        // the weak tag of a compressed response also matches
+        let mut ctx = Context::default();
+        let weak = format!("W/{}", etag);
+        let ret = handler
+            .handle(&request(&[("if-none-match", &weak)]), &mut ctx)
+            .await
+            .unwrap_err();
+        assert_eq!(ret.status, 304);
+
+        // If-Modified-Since is ignored when If-None-Match is present
+        let mut ctx = Context::default();
+        let since = HttpDate::from(1_700_000_000u64).to_string();
+        handler
+            .handle(
+                &request(&[
+                    ("if-none-match", "\"other\""),
+                    ("if-modified-since", &since),
+                ]),
+                &mut ctx,
+            )
+            .await
+            .unwrap();
+        assert_eq!(ctx.response().get_status(), 200);
+
+        let mut ctx = Context::default();
+        let ret = handler
+            .handle(&request(&[("if-modified-since", &since)]), &mut ctx)
+            .await
+            .unwrap_err();
+        assert_eq!(ret.status, 304);
+        assert_eq!(ctx.response().get_header("etag"), Some(etag));
+    }
+
+    /// Store that fails to look up the not-found page
+    struct FailingStore {}
+
+    #[async_trait(?Send)]
+    impl AssetStore for FailingStore {
+        fn lookup(&self, path: &str) -> Result<Option<AssetMetadata>, Error> {
+            match path {
+                "404.html" => Err(Error::Other("store unavailable".to_string())),
+                _ => Ok(None),
+            }
+        }
+
+        async fn get(&self, md: &AssetMetadata) -> Result<Bytes, Error> {
+            Err(Error::NoStaticAsset(md.path.clone()))
+        }
+    }
+
+    #[wasm_bindgen_test]
+    async fn not_found_page_error() {
+        let handler = StaticAssetHandler::new(FailingStore {}).with_not_found("/404.html");
+        let mut ctx = Context::default();
+        let req = Request::new(
+            Method::GET,
+            Url::parse("https://example.com/missing").unwrap(),
+            web_sys::Headers::new().unwrap(),
+            None,
+        );
+        assert!(handler.handle(&req, &mut ctx).await.is_err());
+        assert!(ctx.is_internal_error().is_some());
+    }
+
+    #[wasm_bindgen_test]
+    async fn head_headers() {
+        let handler = handler();
+        let mut ctx = Context::default();
+        handler.handle(&request(&[]), &mut ctx).await.unwrap();
+        let get = ctx.take_response();
+
+        let url = format!("https://example.com/js/app.{}.js", HEX);
+        let headers = web_sys::Headers::new().unwrap();
+        headers.set("range", "bytes=0-9").unwrap();
+        let req = Request::new(Method::HEAD, Url::parse(&url).unwrap(), headers, None);
+        let mut ctx = Context::default();
+        handler.handle(&req, &mut ctx).await.unwrap();
+        let head = ctx.take_response();
+        assert_eq!(head.get_status(), 200);
+        assert_eq!(head.get_header("content-length").as_deref(), Some("2000"));
+        for name in ["accept-ranges", "etag", "last-modified", "content-type"].iter() {
+            assert!(head.get_header(name).is_some());
+            assert_eq!(head.get_header(name), get.get_header(name));
+        }
+    }
+
+    #[cfg(feature = "compression")]
+    #[wasm_bindgen_test]
+    async fn hash_headers_with_compression() {
+        let handler = handler();
+        let compression = crate::Compression::default();
+
+        // identity response has the strong ETag and digest headers
+        let req = request(&[]);
+        let mut ctx = Context::default();
+        handler.handle(&req, &mut ctx).await.unwrap();
+        compression.after(&req, &mut ctx).await;
+        let resp = ctx.response();
+        assert_eq!(resp.get_header("content-encoding"), None);
+        assert_eq!(resp.get_header("etag"), Some(format!("\"{}\"", HEX)));
+        assert!(resp.get_header("digest").is_some());
+        assert!(resp.get_header("repr-digest").is_some());
+
+        // compressed response has a weak ETag and no digest headers
+        let req = request(&[("accept-encoding", "gzip")]);
+        let mut ctx = Context::default();
+        handler.handle(&req, &mut ctx).await.unwrap();
+        compression.after(&req, &mut ctx).await;
+        let resp = ctx.response();
+        assert_eq!(resp.get_header("content-encoding").as_deref(), Some("gzip"));
+        assert_eq!(resp.get_header("etag"), Some(format!("W/\"{}\"", HEX)));
+        assert_eq!(resp.get_header("digest"), None);
+        assert_eq!(resp.get_header("repr-digest"), None);
+    }
+}
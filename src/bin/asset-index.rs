@@ -0,0 +1,136 @@
+//! Generates the asset index and upload manifest for a folder of static assets.
+//!
+//! ```text
+//! asset-index <asset_dir> [options]
+//!   -o, --output <file>      write serialized index (default: data/assets.bin)
+//!   -m, --manifest <file>    write upload manifest as json (default: data/manifest.json)
+//!   -x, --exclude <glob>     skip matching files (may be repeated)
+//!   --diff <file>            compare with previous manifest
+//!   --bulk <file>            with --diff: write new assets for `wrangler kv:bulk put`
+//!   --stale <file>           with --diff: write stale keys for `wrangler kv:bulk delete`
+//! ```
+//! Without `--diff`, `--bulk` writes all assets.
+//!
+//! Requires the `cli` feature: `cargo run --features cli --bin asset-index -- public`
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    if let Err(msg) = native::run(std::env::args().skip(1).collect()) {
+        eprintln!("Error: {}", msg);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn main() {}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use std::path::{Path, PathBuf};
+    use wasm_service::{IndexBuilder, UploadManifest};
+
+    const USAGE: &str = "usage: asset-index <asset_dir> [-o index.bin] [-m manifest.json] \
+                         [-x glob]... [--diff previous.json] [--bulk bulk.json] [--stale stale.json]";
+
+    #[derive(Default)]
+    struct Args {
+        asset_dir: Option<PathBuf>,
+        output: Option<PathBuf>,
+        manifest: Option<PathBuf>,
+        exclude: Vec<String>,
+        diff: Option<PathBuf>,
+        bulk: Option<PathBuf>,
+        stale: Option<PathBuf>,
+    }
+
+    fn parse_args(args: Vec<String>) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {}", arg))
+            };
+            match arg.as_str() {
+                "-o" | "--output" => parsed.output = Some(value()?.into()),
+                "-m" | "--manifest" => parsed.manifest = Some(value()?.into()),
+                "-x" | "--exclude" => parsed.exclude.push(value()?),
+                "--diff" => parsed.diff = Some(value()?.into()),
+                "--bulk" => parsed.bulk = Some(value()?.into()),
+                "--stale" => parsed.stale = Some(value()?.into()),
+                "-h" | "--help" => return Err(USAGE.to_string()),
+                _ if arg.starts_with('-') => {
+                    return Err(format!("unknown option {}\n{}", arg, USAGE))
+                }
+                _ if parsed.asset_dir.is_none() => parsed.asset_dir = Some(arg.into()),
+                _ => return Err(USAGE.to_string()),
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn write(path: &Path, data: &[u8]) -> Result<(), String> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+        }
+        std::fs::write(path, data).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub(crate) fn run(args: Vec<String>) -> Result<(), String> {
+        let args = parse_args(args)?;
+        let asset_dir = args.asset_dir.ok_or_else(|| USAGE.to_string())?;
+        let builder = args
+            .exclude
+            .iter()
+            .fold(IndexBuilder::new(&asset_dir), |builder, glob| {
+                builder.exclude(glob)
+            });
+        let (index, manifest) = builder.build().map_err(|e| e.to_string())?;
+
+        let output = args.output.unwrap_or_else(|| "data/assets.bin".into());
+        write(
+            &output,
+            &IndexBuilder::serialize(&index).map_err(|e| e.to_string())?,
+        )?;
+        let manifest_path = args.manifest.unwrap_or_else(|| "data/manifest.json".into());
+        write(
+            &manifest_path,
+            &manifest.to_json().map_err(|e| e.to_string())?,
+        )?;
+        println!(
+            "Wrote index of {} assets to {}, manifest to {}",
+            manifest.entries.len(),
+            output.display(),
+            manifest_path.display()
+        );
+
+        let (upload, stale) = match &args.diff {
+            Some(previous) => {
+                let data = std::fs::read(previous)
+                    .map_err(|e| format!("{}: {}", previous.display(), e))?;
+                let previous = UploadManifest::from_json(&data).map_err(|e| e.to_string())?;
+                let diff = manifest.diff(previous.entries.into_iter().map(|entry| entry.key));
+                println!(
+                    "{} assets to upload, {} stale keys",
+                    diff.upload.len(),
+                    diff.stale.len()
+                );
+                (diff.upload, diff.stale)
+            }
+            None => (manifest.entries.clone(), Vec::new()),
+        };
+        if let Some(bulk) = &args.bulk {
+            write(
+                bulk,
+                &UploadManifest::bulk_json(&upload).map_err(|e| e.to_string())?,
+            )?;
+        }
+        if let Some(stale_path) = &args.stale {
+            write(
+                stale_path,
+                &serde_json::to_vec(&stale).map_err(|e| e.to_string())?,
+            )?;
+        }
+        Ok(())
+    }
+}
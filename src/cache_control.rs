@@ -0,0 +1,203 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Builder for Cache-Control response header values.
+/// Use with [`Response::cache_control`](crate::Response::cache_control).
+///
+///```
+/// # use wasm_service::CacheControl;
+/// # use std::time::Duration;
+/// let cc = CacheControl::new()
+///     .public()
+///     .max_age(Duration::from_secs(31536000))
+///     .immutable();
+/// assert_eq!(cc.to_string(), "public, max-age=31536000, immutable");
+///```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CacheControl {
+    public: bool,
+    private: bool,
+    no_cache: bool,
+    no_store: bool,
+    no_transform: bool,
+    must_revalidate: bool,
+    proxy_revalidate: bool,
+    immutable: bool,
+    max_age: Option<u64>,
+    s_maxage: Option<u64>,
+    stale_while_revalidate: Option<u64>,
+    stale_if_error: Option<u64>,
+}
+
+impl CacheControl {
+    /// Creates an empty Cache-Control value
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Response may be stored by shared caches (such as the CDN)
+    pub fn public(mut self) -> Self {
+        self.public = true;
+        self
+    }
+
+    /// Response is for a single user, and must not be stored by shared caches
+    pub fn private(mut self) -> Self {
+        self.private = true;
+        self
+    }
+
+    /// Response may be stored, but must be revalidated with the origin before each use
+    pub fn no_cache(mut self) -> Self {
+        self.no_cache = true;
+        self
+    }
+
+    /// Response must not be stored by any cache
+    pub fn no_store(mut self) -> Self {
+        self.no_store = true;
+        self
+    }
+
+    /// Intermediaries must not modify the body (for example, by compressing it)
+    pub fn no_transform(mut self) -> Self {
+        self.no_transform = true;
+        self
+    }
+
+    /// Stale responses must be revalidated before use
+    pub fn must_revalidate(mut self) -> Self {
+        self.must_revalidate = true;
+        self
+    }
+
+    /// Stale responses must be revalidated by shared caches before use
+    pub fn proxy_revalidate(mut self) -> Self {
+        self.proxy_revalidate = true;
+        self
+    }
+
+    /// Response will not change while it is fresh, so clients need not revalidate it.
+    /// Suitable for assets whose file name includes a content hash.
+    pub fn immutable(mut self) -> Self {
+        self.immutable = true;
+        self
+    }
+
+    /// Time that the response remains fresh
+    pub fn max_age(mut self, age: Duration) -> Self {
+        self.max_age = Some(age.as_secs());
+        self
+    }
+
+    /// Time that the response remains fresh in shared caches, overriding max-age
+    pub fn s_maxage(mut self, age: Duration) -> Self {
+        self.s_maxage = Some(age.as_secs());
+        self
+    }
+
+    /// Time after the response becomes stale during which it may be used
+    /// while it is revalidated in the background
+    pub fn stale_while_revalidate(mut self, time: Duration) -> Self {
+        self.stale_while_revalidate = Some(time.as_secs());
+        self
+    }
+
+    /// Time after the response becomes stale during which it may be used
+    /// if revalidation fails with an error
+    pub fn stale_if_error(mut self, time: Duration) -> Self {
+        self.stale_if_error = Some(time.as_secs());
+        self
+    }
+
+    /// Returns the max-age in seconds, if set
+    pub fn get_max_age(&self) -> Option<u64> {
+        self.max_age
+    }
+
+    /// Returns the s-maxage in seconds, if set
+    pub fn get_s_maxage(&self) -> Option<u64> {
+        self.s_maxage
+    }
+
+    /// Returns true if the response may be stored by shared caches:
+    /// it is not private or no-store
+    pub fn is_shared_cacheable(&self) -> bool {
+        !self.private && !self.no_store
+    }
+
+    /// Returns the time, in seconds, that a shared cache may serve the response
+    /// without revalidation (s-maxage, or max-age), or None if the response
+    /// may not be stored by shared caches, requires revalidation (no-cache), or has no lifetime
+    pub fn shared_ttl(&self) -> Option<u64> {
+        if !self.is_shared_cacheable() || self.no_cache {
+            return None;
+        }
+        self.s_maxage.or(self.max_age).filter(|ttl| *ttl > 0)
+    }
+
+    /// Parses a Cache-Control header value. Unknown directives and invalid values are ignored.
+    pub fn parse(value: &str) -> Self {
+        let mut cc = CacheControl::default();
+        for directive in value.split(',') {
+            let mut parts = directive.splitn(2, '=');
+            let name = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+            let secs = parts
+                .next()
+                .and_then(|v| v.trim().trim_matches('"').parse::<u64>().ok());
+            match name.as_str() {
+                "public" => cc.public = true,
+                "private" => cc.private = true,
+                "no-cache" => cc.no_cache = true,
+                "no-store" => cc.no_store = true,
+                "no-transform" => cc.no_transform = true,
+                "must-revalidate" => cc.must_revalidate = true,
+                "proxy-revalidate" => cc.proxy_revalidate = true,
+                "immutable" => cc.immutable = true,
+                "max-age" => cc.max_age = secs,
+                "s-maxage" => cc.s_maxage = secs,
+                "stale-while-revalidate" => cc.stale_while_revalidate = secs,
+                "stale-if-error" => cc.stale_if_error = secs,
+                _ => {}
+            }
+        }
+        cc
+    }
+}
+
+/// Formats the header value, with directives separated by ", "
+impl fmt::Display for CacheControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flags = [
+            (self.public, "public"),
+            (self.private, "private"),
+            (self.no_cache, "no-cache"),
+            (self.no_store, "no-store"),
+            (self.no_transform, "no-transform"),
+            (self.must_revalidate, "must-revalidate"),
+            (self.proxy_revalidate, "proxy-revalidate"),
+        ];
+        let times = [
+            (self.max_age, "max-age"),
+            (self.s_maxage, "s-maxage"),
+            (self.stale_while_revalidate, "stale-while-revalidate"),
+            (self.stale_if_error, "stale-if-error"),
+        ];
+        let directives = flags
+            .iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, name)| name.to_string())
+            .chain(
+                times
+                    .iter()
+                    .filter_map(|(val, name)| val.map(|v| format!("{}={}", name, v))),
+            )
+            .chain(if self.immutable {
+                Some("immutable".to_string())
+            } else {
+                None
+            })
+            .collect::<Vec<String>>();
+        f.write_str(&directives.join(", "))
+    }
+}
@@ -0,0 +1,393 @@
+use crate::accept_encoding::accepted_encodings;
+use crate::etag::weak_etag;
+use crate::{media_type, Context, Error, Middleware, Request};
+use async_trait::async_trait;
+use service_logging::{log, Severity};
+use std::io::{Read, Write};
+
+/// Limit on decompressed request body size, if no maximum body size is configured
+pub(crate) const DEFAULT_MAX_DECOMPRESSED_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Configuration for response [`Compression`]
+#[derive(Clone, Debug)]
+pub struct CompressionConfig {
+    /// Bodies smaller than this (in bytes) are not compressed. Default: 1024
+    pub min_size: usize,
+    /// Enable gzip encoding. Default: true
+    pub gzip: bool,
+    /// Enable brotli encoding. Default: true
+    pub brotli: bool,
+    /// gzip compression level, 0-9. Default: 6
+    pub gzip_level: u32,
+    /// brotli quality, 0-11. Higher levels are slow, so the default (5)
+    /// is a compromise suitable for dynamic content.
+    pub brotli_quality: u32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        CompressionConfig {
+            min_size: 1024,
+            gzip: true,
+            brotli: true,
+            gzip_level: 6,
+            brotli_quality: 5,
+        }
+    }
+}
+
+/// Middleware that compresses response bodies with gzip or brotli,
+/// according to the client's `Accept-Encoding` header.
+/// Brotli is preferred when the client accepts both with equal preference.
+///
+/// Responses are not compressed if the body is a stream, is smaller than `min_size`,
+/// already has a `Content-Encoding`, or has `Cache-Control: no-transform`.
+/// The media type is taken from the response `Content-Type`, or if that is not set,
+/// from the request path with [`media_type`]; unknown types and types that are
+/// already compressed (images, audio, video, archives, fonts, and pdf) are skipped.
+/// When a response could be compressed, `Vary: Accept-Encoding` is added so caches
+/// keep separate copies for each encoding.
+/// A strong `ETag` on a compressed response is changed to a weak tag, and `Digest` and
+/// `Repr-Digest` headers are removed, since they no longer describe the bytes of the body.
+///
+/// Requires the `compression` feature.
+///
+///```rust,ignore
+/// let config = ServiceConfig {
+///     middleware: vec![Box::new(Compression::default())],
+///     ..Default::default()
+/// };
+///```
+#[derive(Debug, Default)]
+pub struct Compression {
+    config: CompressionConfig,
+}
+
+impl Compression {
+    /// Creates compression middleware with the configuration
+    pub fn new(config: CompressionConfig) -> Self {
+        Compression { config }
+    }
+}
+
+#[async_trait(?Send)]
+impl Middleware for Compression {
+    async fn after(&self, req: &Request, ctx: &mut Context) {
+        let resp = ctx.response();
+        let status = resp.get_status();
+        if status < 200 || status == 204 || status == 206 || status == 304 {
+            return;
+        }
+        if resp.is_stream()
+            || resp.get_body().len() < self.config.min_size
+            || resp.get_header("content-encoding").is_some()
+        {
+            return;
+        }
+        if let Some(cc) = resp.get_header("cache-control") {
+            if cc.to_ascii_lowercase().contains("no-transform") {
+                return;
+            }
+        }
+        let compressible = match resp.get_header("content-type") {
+            Some(ctype) => is_compressible(&ctype),
+            None => media_type(req.url().path())
+                .map(is_compressible)
+                .unwrap_or(false),
+        };
+        if !compressible {
+            return;
+        }
+        // unwrap ok because header values are ascii
+        resp.append_header("vary", "Accept-Encoding").unwrap();
+        let encoding = match req
+            .get_header("accept-encoding")
+            .and_then(|accept| negotiate(&accept, &self.config))
+        {
+            Some(encoding) => encoding,
+            None => return,
+        };
+        match encoding.compress(resp.get_body(), &self.config) {
+            Ok(compressed) if compressed.len() < resp.get_body().len() => {
+                resp.header("content-encoding", encoding.as_str())
+                    .unwrap()
+                    .body(compressed);
+                if let Some(etag) = resp.get_header("etag") {
+                    resp.header("etag", weak_etag(&etag)).unwrap();
+                }
+                // a length set by the handler is for the uncompressed body;
+                // the runtime sets the length of the compressed body
+                resp.remove_header("content-length")
+                    .unwrap()
+                    .remove_header("digest")
+                    .unwrap()
+                    .remove_header("repr-digest")
+                    .unwrap();
+            }
+            Ok(_) => {}
+            Err(e) => {
+                log!(ctx, Severity::Warning, _:"compression", encoding: encoding.as_str(), error: e);
+            }
+        }
+    }
+}
+
+/// Content encodings supported for compression
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Encoding {
+    Gzip,
+    Brotli,
+}
+
+impl Encoding {
+    /// Returns the name used in Accept-Encoding and Content-Encoding headers
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Brotli => "br",
+        }
+    }
+
+    fn compress(&self, data: &[u8], config: &CompressionConfig) -> std::io::Result<Vec<u8>> {
+        match self {
+            Encoding::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(
+                    Vec::new(),
+                    flate2::Compression::new(config.gzip_level),
+                );
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            Encoding::Brotli => {
+                // 22 is the default window size (lgwin) for brotli
+                let mut encoder =
+                    brotli::CompressorWriter::new(Vec::new(), 4096, config.brotli_quality, 22);
+                encoder.write_all(data)?;
+                encoder.flush()?;
+                Ok(encoder.into_inner())
+            }
+        }
+    }
+}
+
+/// Decodes data with the Content-Encoding, which may be a comma-separated list
+/// of encodings, in the order they were applied. Fails with PayloadTooLarge
+/// if the output of any step exceeds `limit` bytes.
+pub(crate) fn decode(content_encoding: &str, data: &[u8], limit: u64) -> Result<Vec<u8>, Error> {
+    let mut buf = data.to_vec();
+    for encoding in content_encoding.rsplit(',') {
+        let encoding = encoding.trim().to_ascii_lowercase();
+        buf = match encoding.as_str() {
+            "gzip" | "x-gzip" => read_limited(flate2::read::MultiGzDecoder::new(&buf[..]), limit)?,
+            // "deflate" should be zlib format, but some clients send raw deflate
+            "deflate" => match read_limited(flate2::read::ZlibDecoder::new(&buf[..]), limit) {
+                Err(Error::Decompress(_)) => {
+                    read_limited(flate2::read::DeflateDecoder::new(&buf[..]), limit)?
+                }
+                result => result?,
+            },
+            "br" => read_limited(brotli::Decompressor::new(&buf[..], 4096), limit)?,
+            "identity" | "" => buf,
+            _ => return Err(Error::UnsupportedEncoding(encoding)),
+        };
+    }
+    Ok(buf)
+}
+
+/// Reads decoder output, up to limit bytes
+fn read_limited<R: Read>(decoder: R, limit: u64) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::new();
+    decoder
+        .take(limit + 1)
+        .read_to_end(&mut out)
+        .map_err(|e| Error::Decompress(e.to_string()))?;
+    if out.len() as u64 > limit {
+        return Err(Error::PayloadTooLarge(limit));
+    }
+    Ok(out)
+}
+
+/// Selects an encoding from the Accept-Encoding header value.
+/// Returns None if no enabled encoding is acceptable.
+fn negotiate(accept: &str, config: &CompressionConfig) -> Option<Encoding> {
+    let mut supported = Vec::new();
+    if config.brotli {
+        supported.push(Encoding::Brotli.as_str());
+    }
+    if config.gzip {
+        supported.push(Encoding::Gzip.as_str());
+    }
+    match accepted_encodings(accept, &supported).first() {
+        Some(&"br") => Some(Encoding::Brotli),
+        Some(_) => Some(Encoding::Gzip),
+        None => None,
+    }
+}
+
+/// Returns true if content of the media type is likely to benefit from compression.
+/// Images (except svg), audio, video, fonts, and archive formats are already compressed.
+fn is_compressible(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    if essence == "image/svg+xml" {
+        return true;
+    }
+    if essence.starts_with("image/")
+        || essence.starts_with("audio/")
+        || essence.starts_with("video/")
+        || essence.starts_with("font/woff")
+    {
+        return false;
+    }
+    !matches!(
+        essence.as_str(),
+        "application/octet-stream"
+            | "application/zip"
+            | "application/gzip"
+            | "application/x-gzip"
+            | "application/x-bzip2"
+            | "application/x-7z-compressed"
+            | "application/x-rar-compressed"
+            | "application/zstd"
+            | "application/pdf"
+            | "application/font-woff"
+            | ""
+    )
+}
+
+#[test]
+fn negotiate_encoding() {
+    let config = CompressionConfig::default();
+    assert_eq!(
+        negotiate("gzip, deflate, br", &config),
+        Some(Encoding::Brotli)
+    );
+    assert_eq!(negotiate("gzip", &config), Some(Encoding::Gzip));
+    assert_eq!(negotiate("br;q=0.5, gzip", &config), Some(Encoding::Gzip));
+    assert_eq!(negotiate("gzip;q=0, br;q=0", &config), None);
+    assert_eq!(negotiate("identity", &config), None);
+    assert_eq!(negotiate("*", &config), Some(Encoding::Brotli));
+    assert_eq!(negotiate("br;q=0, *;q=0.1", &config), Some(Encoding::Gzip));
+    assert_eq!(negotiate("", &config), None);
+
+    let gzip_only = CompressionConfig {
+        brotli: false,
+        ..Default::default()
+    };
+    assert_eq!(negotiate("br, gzip", &gzip_only), Some(Encoding::Gzip));
+    assert_eq!(negotiate("br", &gzip_only), None);
+}
+
+#[test]
+fn compressible_types() {
+    assert!(is_compressible("text/html; charset=utf-8"));
+    assert!(is_compressible("application/json"));
+    assert!(is_compressible("application/wasm"));
+    assert!(is_compressible("image/svg+xml"));
+    assert!(!is_compressible("image/png"));
+    assert!(!is_compressible("video/mp4"));
+    assert!(!is_compressible("application/octet-stream"));
+    assert!(!is_compressible("application/pdf"));
+    assert!(!is_compressible("font/woff2"));
+}
+
+#[test]
+fn compress_roundtrip() {
+    let data = "hello world ".repeat(200);
+    let config = CompressionConfig::default();
+
+    let gz = Encoding::Gzip.compress(data.as_bytes(), &config).unwrap();
+    assert!(gz.len() < data.len());
+    let mut out = String::new();
+    flate2::read::GzDecoder::new(&gz[..])
+        .read_to_string(&mut out)
+        .unwrap();
+    assert_eq!(out, data);
+
+    let br = Encoding::Brotli.compress(data.as_bytes(), &config).unwrap();
+    assert!(br.len() < data.len());
+    let mut out = String::new();
+    brotli::Decompressor::new(&br[..], 4096)
+        .read_to_string(&mut out)
+        .unwrap();
+    assert_eq!(out, data);
+}
+
+#[test]
+fn decode_request_body() {
+    let data = "{\"hello\":\"world\"}".repeat(100);
+    let config = CompressionConfig::default();
+    let gz = Encoding::Gzip.compress(data.as_bytes(), &config).unwrap();
+    let br = Encoding::Brotli.compress(data.as_bytes(), &config).unwrap();
+
+    assert_eq!(decode("gzip", &gz, 1 << 20).unwrap(), data.as_bytes());
+    assert_eq!(decode("BR", &br, 1 << 20).unwrap(), data.as_bytes());
+    assert_eq!(decode("identity", b"abc", 1 << 20).unwrap(), b"abc");
+
+    // multiple encodings are removed in reverse order
+    let br_gz = Encoding::Gzip.compress(&br, &config).unwrap();
+    assert_eq!(
+        decode("br, gzip", &br_gz, 1 << 20).unwrap(),
+        data.as_bytes()
+    );
+
+    // zlib and raw deflate
+    let mut zlib = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    zlib.write_all(data.as_bytes()).unwrap();
+    let zlib = zlib.finish().unwrap();
+    assert_eq!(decode("deflate", &zlib, 1 << 20).unwrap(), data.as_bytes());
+    let mut raw = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+    raw.write_all(data.as_bytes()).unwrap();
+    let raw = raw.finish().unwrap();
+    assert_eq!(decode("deflate", &raw, 1 << 20).unwrap(), data.as_bytes());
+
+    assert!(matches!(
+        decode("gzip", &gz, 100),
+        Err(Error::PayloadTooLarge(100))
+    ));
+    assert!(matches!(
+        decode("compress", &gz, 1 << 20),
+        Err(Error::UnsupportedEncoding(_))
+    ));
+    assert!(matches!(
+        decode("gzip", b"not gzip", 1 << 20),
+        Err(Error::Decompress(_))
+    ));
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod test {
+    use super::*;
+    use crate::{Method, Url};
+    use wasm_bindgen_test::*;
+
+    #[wasm_bindgen_test]
+    async fn compressed_headers() {
+        let headers = web_sys::Headers::new().unwrap();
+        headers.set("accept-encoding", "gzip").unwrap();
+        let req = Request::new(
+            Method::GET,
+            Url::parse("https://example.com/a.txt").unwrap(),
+            headers,
+            None,
+        );
+        let body = "hello ".repeat(500);
+        let mut ctx = Context::default();
+        ctx.response()
+            .header("content-length", body.len().to_string())
+            .unwrap()
+            .header("etag", "\"v1\"")
+            .unwrap()
+            .text(body);
+        Compression::default().after(&req, &mut ctx).await;
+        let resp = ctx.response();
+        assert_eq!(resp.get_header("content-encoding").as_deref(), Some("gzip"));
+        assert_eq!(resp.get_header("content-length"), None);
+        assert_eq!(resp.get_header("etag").as_deref(), Some("W/\"v1\""));
+        assert_eq!(resp.get_header("vary").as_deref(), Some("Accept-Encoding"));
+    }
+}
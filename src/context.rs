@@ -1,7 +1,10 @@
 use crate::Response;
 use crate::Runnable;
+use crate::{DurableStorage, Env};
+use futures::future::LocalBoxFuture;
 use service_logging::{LogEntry, LogQueue};
-use std::panic::UnwindSafe;
+use std::{future::Future, panic::UnwindSafe};
+use url::Url;
 
 /// Context manages the information flow for an incoming HTTP [`Request`],
 /// the application handler, and the generated HTTP [`Response`]. It holds a buffer
@@ -11,7 +14,11 @@ pub struct Context {
     response: Response,
     log_queue: LogQueue,
     deferred: Vec<Box<dyn Runnable + UnwindSafe>>,
+    deferred_local: Vec<LocalBoxFuture<'static, ()>>,
     internal_error: Option<Box<dyn std::error::Error>>,
+    env: Env,
+    storage: Option<DurableStorage>,
+    rewrite: Option<Url>,
 }
 
 unsafe impl Send for Context {}
@@ -22,6 +29,26 @@ impl Context {
         &mut self.response
     }
 
+    /// Returns the worker environment bindings (vars, secrets, and KV namespaces)
+    pub fn env(&self) -> &Env {
+        &self.env
+    }
+
+    /// Sets the worker environment bindings
+    pub fn set_env(&mut self, env: Env) {
+        self.env = env;
+    }
+
+    /// Returns transactional storage, if this request is being processed by a Durable Object
+    pub fn storage(&self) -> Option<&DurableStorage> {
+        self.storage.as_ref()
+    }
+
+    /// Sets the Durable Object storage
+    pub(crate) fn set_storage(&mut self, storage: DurableStorage) {
+        self.storage = Some(storage);
+    }
+
     /// Adds a task to the deferred task queue. The task queue uses
     /// [event.waitUntil](https://developers.cloudflare.com/workers/runtime-apis/fetch-event)
     /// to extend the lifetime of the request event, and runs tasks after the response
@@ -31,6 +58,25 @@ impl Context {
         self.deferred.push(task);
     }
 
+    /// Adds a future to the deferred task queue, to be run after the response has been returned,
+    /// like [`defer`](Context::defer). Unlike Runnable tasks, the future does not need to be Send,
+    /// so it can hold javascript values and await javascript promises.
+    /// Deferred futures run after all Runnable tasks.
+    pub fn defer_local<F: Future<Output = ()> + 'static>(&mut self, fut: F) {
+        self.deferred_local.push(Box::pin(fut));
+    }
+
+    /// Changes the url of the request for the middleware and handlers that run after this one,
+    /// without redirecting the client. The url should have the same origin as the request.
+    pub fn rewrite(&mut self, url: Url) {
+        self.rewrite = Some(url);
+    }
+
+    /// Returns the url set by [`rewrite`](Context::rewrite), clearing it
+    pub(crate) fn take_rewrite(&mut self) -> Option<Url> {
+        self.rewrite.take()
+    }
+
     /// Returns pending log messages, emptying internal queue.
     /// This is used for sending queued messages to an external log service
     pub fn take_logs(&mut self) -> Vec<LogEntry> {
@@ -42,6 +88,11 @@ impl Context {
         std::mem::take(&mut self.deferred)
     }
 
+    /// Returns deferred futures, emptying internal list
+    pub(crate) fn take_local_tasks(&mut self) -> Vec<LocalBoxFuture<'static, ()>> {
+        std::mem::take(&mut self.deferred_local)
+    }
+
     /// Returns response, replacing self.response with default
     pub(crate) fn take_response(&mut self) -> Response {
         std::mem::take(&mut self.response)
@@ -0,0 +1,408 @@
+use crate::js_values::{call_method, get_prop, resolve, set_prop};
+use crate::{
+    dispatch_fetch, finish_event, Context, Env, Error, Handler, HandlerReturn, HttpDate, Method,
+    Request, ServiceConfig,
+};
+use async_trait::async_trait;
+use bytes::Bytes;
+use js_sys::{Array, Object, Uint8Array};
+use serde::{de::DeserializeOwned, Serialize};
+use service_logging::{log, Logger, Severity};
+use wasm_bindgen::JsValue;
+
+/// Trait for implementing a Durable Object class in Rust.
+/// Requests are routed to `fetch`, which has the same signature as [`Handler::handle`],
+/// and alarms are delivered to `alarm`. Transactional storage is available
+/// from [`Context::storage`](crate::Context::storage).
+///
+/// The javascript class for the object should keep an instance of the rust object
+/// (created in its constructor), and call [`durable_object_fetch`] and [`durable_object_alarm`]
+/// from its `fetch` and `alarm` methods.
+#[async_trait(?Send)]
+pub trait DurableObject {
+    /// Process a request sent to this object
+    async fn fetch(&self, req: &Request, ctx: &mut Context) -> Result<(), HandlerReturn>;
+
+    /// Process an alarm. The default implementation does nothing.
+    async fn alarm(&self, _ctx: &mut Context) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Adapter so a DurableObject can run in the fetch handler chain
+struct DurableFetch<'obj, D: ?Sized>(&'obj D);
+
+#[async_trait(?Send)]
+impl<'obj, D: DurableObject + ?Sized> Handler for DurableFetch<'obj, D> {
+    async fn handle(&self, req: &Request, ctx: &mut Context) -> Result<(), HandlerReturn> {
+        self.0.fetch(req, ctx).await
+    }
+}
+
+/// Entrypoint for requests to a Durable Object. The request is processed like [`service_request`](crate::service_request):
+/// `object.fetch` is invoked first, followed by `config.handlers` if the response was not set,
+/// and the config's not-found and internal error handlers and logger are used.
+/// The parameter is a javascript Map with the same keys as for `service_request`, plus:
+/// - "state": the DurableObjectState passed to the object constructor
+///
+/// Because Durable Objects don't receive a fetch event, "event" should also be set to the state,
+/// whose `waitUntil` is used for deferred tasks.
+pub async fn durable_object_fetch<D: DurableObject + ?Sized>(
+    object: &D,
+    input: JsValue,
+    config: ServiceConfig,
+) -> Result<JsValue, JsValue> {
+    let map = js_sys::Map::from(input);
+    let ctx = durable_context(&map);
+    dispatch_fetch(&map, ctx, Some(&DurableFetch(object)), config).await
+}
+
+/// Entrypoint for Durable Object alarms. The parameter is a javascript Map with keys
+/// "state", "event" (also set to the state), and (optionally) "env".
+pub async fn durable_object_alarm<D: DurableObject + ?Sized>(
+    object: &D,
+    input: JsValue,
+    logger: Box<dyn Logger>,
+) -> Result<(), JsValue> {
+    let map = js_sys::Map::from(input);
+    let mut ctx = durable_context(&map);
+    let result = object.alarm(&mut ctx).await;
+    let error = match (&result, ctx.is_internal_error()) {
+        (Err(e), _) => Some(e.to_string()),
+        (Ok(_), Some(e)) => Some(e.to_string()),
+        _ => None,
+    };
+    match error {
+        Some(ref e) => log!(ctx, Severity::Error, _:"alarm", error: e),
+        None => log!(ctx, Severity::Info, _:"alarm"),
+    }
+    finish_event(&map, &mut ctx, logger, "alarm", error.is_some()).await?;
+    match error {
+        Some(e) => Err(JsValue::from_str(&e)),
+        None => Ok(()),
+    }
+}
+
+fn durable_context(map: &js_sys::Map) -> Context {
+    let mut ctx = Context::default();
+    ctx.set_env(Env::from(map.get(&"env".into())));
+    if let Some(storage) = get_prop(&map.get(&"state".into()), "storage") {
+        ctx.set_storage(DurableStorage { inner: storage });
+    }
+    ctx
+}
+
+/// Transactional storage for a Durable Object.
+/// Values are serialized to json with serde. Each operation is atomic,
+/// and a sequence of operations without intervening awaits on other i/o
+/// is applied together, as described in the Durable Objects documentation.
+#[derive(Clone, Debug)]
+pub struct DurableStorage {
+    inner: JsValue,
+}
+
+impl DurableStorage {
+    /// Returns the value stored at the key, or None if the key is not present.
+    /// Returns an error if the stored value is not a json string (for example,
+    /// if it was stored by javascript without serializing it).
+    pub async fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, Error> {
+        let val = resolve(call_method(
+            &self.inner,
+            "get",
+            &Array::of1(&JsValue::from_str(key)),
+        )?)
+        .await?;
+        if val.is_undefined() {
+            return Ok(None);
+        }
+        match val.as_string() {
+            Some(json) => Ok(Some(serde_json::from_str(&json)?)),
+            None => Err(Error::Js(format!(
+                "storage value for key({}) is not a json string",
+                key
+            ))),
+        }
+    }
+
+    /// Stores the value at the key
+    pub async fn put<T: Serialize>(&self, key: &str, value: &T) -> Result<(), Error> {
+        let json = serde_json::to_string(value)?;
+        resolve(call_method(
+            &self.inner,
+            "put",
+            &Array::of2(&JsValue::from_str(key), &JsValue::from_str(&json)),
+        )?)
+        .await?;
+        Ok(())
+    }
+
+    /// Deletes the key. Returns true if the key was present.
+    pub async fn delete(&self, key: &str) -> Result<bool, Error> {
+        let val = resolve(call_method(
+            &self.inner,
+            "delete",
+            &Array::of1(&JsValue::from_str(key)),
+        )?)
+        .await?;
+        Ok(val.as_bool().unwrap_or(false))
+    }
+
+    /// Returns all keys and values, optionally limited to keys beginning with the prefix,
+    /// in key order. Returns an error if any stored value is not a json string.
+    pub async fn list<T: DeserializeOwned>(
+        &self,
+        prefix: Option<&str>,
+    ) -> Result<Vec<(String, T)>, Error> {
+        let opts = Object::new();
+        if let Some(prefix) = prefix {
+            set_prop(&opts, "prefix", JsValue::from_str(prefix))?;
+        }
+        let map = js_sys::Map::from(
+            resolve(call_method(&self.inner, "list", &Array::of1(&opts))?).await?,
+        );
+        let mut entries = Vec::new();
+        let mut result = Ok(());
+        map.for_each(&mut |val, key| {
+            let key = key.as_string().unwrap_or_default();
+            match val.as_string() {
+                Some(json) => match serde_json::from_str(&json) {
+                    Ok(val) => entries.push((key, val)),
+                    Err(e) => result = Err(Error::from(e)),
+                },
+                None => {
+                    result = Err(Error::Js(format!(
+                        "storage value for key({}) is not a json string",
+                        key
+                    )))
+                }
+            }
+        });
+        result.map(|_| entries)
+    }
+
+    /// Returns the time of the scheduled alarm, or None if no alarm is set
+    pub async fn get_alarm(&self) -> Result<Option<HttpDate>, Error> {
+        let val = resolve(call_method(&self.inner, "getAlarm", &Array::new())?).await?;
+        Ok(val.as_f64().map(|ms| HttpDate::from((ms / 1000.0) as u64)))
+    }
+
+    /// Schedules the alarm, replacing any previously scheduled alarm
+    pub async fn set_alarm(&self, time: HttpDate) -> Result<(), Error> {
+        let ms = JsValue::from_f64(time.timestamp() as f64 * 1000.0);
+        resolve(call_method(&self.inner, "setAlarm", &Array::of1(&ms))?).await?;
+        Ok(())
+    }
+
+    /// Cancels the scheduled alarm, if any
+    pub async fn delete_alarm(&self) -> Result<(), Error> {
+        resolve(call_method(&self.inner, "deleteAlarm", &Array::new())?).await?;
+        Ok(())
+    }
+}
+
+/// Durable Object namespace binding, obtained from [`Env::durable_object`](crate::Env::durable_object).
+/// Used by ordinary handlers to send requests to objects.
+#[derive(Clone, Debug)]
+pub struct DurableObjectNamespace {
+    inner: JsValue,
+}
+
+/// Id of a Durable Object
+#[derive(Clone, Debug)]
+pub struct DurableObjectId {
+    inner: JsValue,
+}
+
+impl DurableObjectId {
+    /// Returns the id as a hex string
+    pub fn to_hex(&self) -> String {
+        call_method(&self.inner, "toString", &Array::new())
+            .ok()
+            .and_then(|v| v.as_string())
+            .unwrap_or_default()
+    }
+}
+
+impl DurableObjectNamespace {
+    /// Returns the id of the object with the name.
+    /// The same name always refers to the same object.
+    pub fn id_from_name(&self, name: &str) -> Result<DurableObjectId, Error> {
+        let inner = call_method(
+            &self.inner,
+            "idFromName",
+            &Array::of1(&JsValue::from_str(name)),
+        )?;
+        Ok(DurableObjectId { inner })
+    }
+
+    /// Parses an id previously returned from [`DurableObjectId::to_hex`]
+    pub fn id_from_string(&self, hex: &str) -> Result<DurableObjectId, Error> {
+        let inner = call_method(
+            &self.inner,
+            "idFromString",
+            &Array::of1(&JsValue::from_str(hex)),
+        )?;
+        Ok(DurableObjectId { inner })
+    }
+
+    /// Generates a new, unique, id
+    pub fn new_unique_id(&self) -> Result<DurableObjectId, Error> {
+        let inner = call_method(&self.inner, "newUniqueId", &Array::new())?;
+        Ok(DurableObjectId { inner })
+    }
+
+    /// Returns a stub for sending requests to the object
+    pub fn get(&self, id: &DurableObjectId) -> Result<DurableObjectStub, Error> {
+        let inner = call_method(&self.inner, "get", &Array::of1(&id.inner))?;
+        Ok(DurableObjectStub { inner })
+    }
+
+    /// Returns a stub for the object with the name
+    pub fn get_by_name(&self, name: &str) -> Result<DurableObjectStub, Error> {
+        self.get(&self.id_from_name(name)?)
+    }
+}
+
+impl From<JsValue> for DurableObjectNamespace {
+    fn from(inner: JsValue) -> DurableObjectNamespace {
+        DurableObjectNamespace { inner }
+    }
+}
+
+/// Client stub for a Durable Object
+#[derive(Clone, Debug)]
+pub struct DurableObjectStub {
+    inner: JsValue,
+}
+
+/// Response from a request sent to a Durable Object
+#[derive(Debug)]
+pub struct StubResponse {
+    /// http status
+    pub status: u16,
+    /// response headers
+    pub headers: web_sys::Headers,
+    /// response body
+    pub body: Bytes,
+}
+
+impl DurableObjectStub {
+    /// Sends a request to the object. The url host is not used for routing,
+    /// but it must be a valid absolute url.
+    pub async fn fetch(
+        &self,
+        method: Method,
+        url: &str,
+        headers: Option<&web_sys::Headers>,
+        body: Option<&[u8]>,
+    ) -> Result<StubResponse, Error> {
+        let init = Object::new();
+        set_prop(&init, "method", JsValue::from_str(&method.to_string()))?;
+        if let Some(headers) = headers {
+            set_prop(&init, "headers", JsValue::from(headers.clone()))?;
+        }
+        if let Some(body) = body {
+            set_prop(&init, "body", Uint8Array::from(body).into())?;
+        }
+        let resp = resolve(call_method(
+            &self.inner,
+            "fetch",
+            &Array::of2(&JsValue::from_str(url), &init),
+        )?)
+        .await?;
+        let status = get_prop(&resp, "status")
+            .and_then(|v| v.as_f64())
+            .unwrap_or_default() as u16;
+        let headers = web_sys::Headers::from(get_prop(&resp, "headers").unwrap_or_default());
+        let buf = resolve(call_method(&resp, "arrayBuffer", &Array::new())?).await?;
+        Ok(StubResponse {
+            status,
+            headers,
+            body: Bytes::from(Uint8Array::new(&buf).to_vec()),
+        })
+    }
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod test {
+    use super::*;
+    use serde::Deserialize;
+    use wasm_bindgen_test::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Item {
+        name: String,
+        count: u32,
+    }
+
+    /// In-memory javascript object with the storage methods used by DurableStorage
+    fn mock_storage() -> DurableStorage {
+        let inner = js_sys::Function::new_no_args(
+            "const m = new Map(); return { \
+               get: async k => m.get(k), \
+               put: async (k, v) => { m.set(k, v); }, \
+               delete: async k => m.delete(k), \
+               list: async o => new Map([...m].filter(([k]) => k.startsWith(o.prefix || '')).sort()) \
+             };",
+        )
+        .call0(&JsValue::NULL)
+        .unwrap();
+        DurableStorage { inner }
+    }
+
+    #[wasm_bindgen_test]
+    async fn storage_json() {
+        let storage = mock_storage();
+        let item = Item {
+            name: "a".to_string(),
+            count: 1,
+        };
+        assert_eq!(storage.get::<Item>("item:1").await.unwrap(), None);
+        storage.put("item:1", &item).await.unwrap();
+        storage
+            .put(
+                "item:2",
+                &Item {
+                    name: "b".to_string(),
+                    count: 2,
+                },
+            )
+            .await
+            .unwrap();
+        storage.put("other", &7u32).await.unwrap();
+        assert_eq!(storage.get::<Item>("item:1").await.unwrap(), Some(item));
+        assert_eq!(storage.get::<u32>("other").await.unwrap(), Some(7));
+        assert!(storage.get::<u32>("item:1").await.is_err());
+
+        let items = storage.list::<Item>(Some("item:")).await.unwrap();
+        let keys = items
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.count))
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec![("item:1", 1), ("item:2", 2)]);
+
+        assert!(storage.delete("item:1").await.unwrap());
+        assert!(!storage.delete("item:1").await.unwrap());
+        assert_eq!(storage.list::<Item>(Some("item:")).await.unwrap().len(), 1);
+    }
+
+    #[wasm_bindgen_test]
+    async fn storage_non_string_values() {
+        let storage = mock_storage();
+        storage.put("a", &1u32).await.unwrap();
+        // stored by javascript without serializing
+        resolve(
+            call_method(
+                &storage.inner,
+                "put",
+                &Array::of2(&JsValue::from_str("b"), &JsValue::from_f64(2.0)),
+            )
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+        assert!(storage.get::<u32>("b").await.is_err());
+        assert!(storage.list::<u32>(None).await.is_err());
+        assert_eq!(storage.get::<u32>("a").await.unwrap(), Some(1));
+    }
+}
@@ -0,0 +1,515 @@
+use crate::js_values::{call_method, get_prop, headers_to_vec, resolve, set_prop};
+use crate::{CacheControl, Context, Error, HandlerReturn, Method, Middleware, Request, Response};
+use async_trait::async_trait;
+use bytes::Bytes;
+use js_sys::{Array, Object, Uint8Array};
+use service_logging::{log, Severity};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use wasm_bindgen::JsValue;
+
+/// Header added to responses by [`EdgeCache`]: "HIT" if the response was served from cache,
+/// "MISS" if it was generated by the handlers and stored
+pub const CACHE_STATUS_HEADER: &str = "x-cache";
+
+/// A response stored in a [`CacheStore`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CachedResponse {
+    /// http status
+    pub status: u16,
+    /// response headers
+    pub headers: Vec<(String, String)>,
+    /// response body
+    pub body: Bytes,
+}
+
+impl CachedResponse {
+    /// Copies status, headers, and body of the response.
+    /// Returns None if the body is a stream.
+    pub fn from_response(resp: &Response) -> Option<Self> {
+        if resp.is_stream() {
+            return None;
+        }
+        Some(CachedResponse {
+            status: resp.get_status(),
+            headers: resp.get_headers().map(headers_to_vec).unwrap_or_default(),
+            body: Bytes::copy_from_slice(resp.get_body()),
+        })
+    }
+
+    /// Sets the status, headers, and body of the response
+    pub fn apply(&self, resp: &mut Response) -> Result<(), Error> {
+        for (name, val) in self.headers.iter() {
+            resp.header(name, val)?;
+        }
+        resp.status(self.status).body(self.body.clone());
+        Ok(())
+    }
+}
+
+/// Storage for cached responses, keyed by url
+#[async_trait(?Send)]
+pub trait CacheStore {
+    /// Returns the cached response for the key, or None if it is not cached or has expired
+    async fn get(&self, key: &str) -> Result<Option<CachedResponse>, Error>;
+
+    /// Stores the response. The store may use the response's Cache-Control header
+    /// to determine its lifetime.
+    async fn put(&self, key: &str, response: CachedResponse) -> Result<(), Error>;
+
+    /// Removes the response for the key. Returns true if it was cached.
+    async fn delete(&self, key: &str) -> Result<bool, Error>;
+}
+
+/// [`CacheStore`] using the Workers Cache API. Entries are local to the data center,
+/// and expire according to their Cache-Control header. Keys must be absolute urls.
+#[derive(Clone, Debug)]
+pub struct WorkersCache {
+    inner: JsValue,
+}
+
+impl WorkersCache {
+    /// Returns the default cache (`caches.default`), which is shared with the CDN
+    pub fn default_cache() -> Result<Self, Error> {
+        let inner = get_prop(&js_sys::global(), "caches")
+            .and_then(|caches| get_prop(&caches, "default"))
+            .ok_or_else(|| Error::Js("Cache API is not available".to_string()))?;
+        Ok(WorkersCache { inner })
+    }
+
+    /// Opens a named cache (`caches.open(name)`)
+    pub async fn open(name: &str) -> Result<Self, Error> {
+        let caches = get_prop(&js_sys::global(), "caches")
+            .ok_or_else(|| Error::Js("Cache API is not available".to_string()))?;
+        let inner = resolve(call_method(
+            &caches,
+            "open",
+            &Array::of1(&JsValue::from_str(name)),
+        )?)
+        .await?;
+        Ok(WorkersCache { inner })
+    }
+}
+
+#[async_trait(?Send)]
+impl CacheStore for WorkersCache {
+    async fn get(&self, key: &str) -> Result<Option<CachedResponse>, Error> {
+        let resp = resolve(call_method(
+            &self.inner,
+            "match",
+            &Array::of1(&JsValue::from_str(key)),
+        )?)
+        .await?;
+        if resp.is_undefined() || resp.is_null() {
+            return Ok(None);
+        }
+        let status = get_prop(&resp, "status")
+            .and_then(|v| v.as_f64())
+            .unwrap_or_default() as u16;
+        let headers = headers_to_vec(&web_sys::Headers::from(
+            get_prop(&resp, "headers").unwrap_or_default(),
+        ));
+        let buf = resolve(call_method(&resp, "arrayBuffer", &Array::new())?).await?;
+        Ok(Some(CachedResponse {
+            status,
+            headers,
+            body: Bytes::from(Uint8Array::new(&buf).to_vec()),
+        }))
+    }
+
+    async fn put(&self, key: &str, response: CachedResponse) -> Result<(), Error> {
+        let headers = web_sys::Headers::new()?;
+        for (name, val) in response.headers.iter() {
+            headers.set(name, val)?;
+        }
+        let init = Object::new();
+        set_prop(&init, "status", JsValue::from_f64(response.status as f64))?;
+        set_prop(&init, "headers", JsValue::from(headers))?;
+        let ctor = get_prop(&js_sys::global(), "Response")
+            .ok_or_else(|| Error::Js("Response is not available".to_string()))?;
+        let js_resp = js_sys::Reflect::construct(
+            &js_sys::Function::from(ctor),
+            &Array::of2(&Uint8Array::from(response.body.as_ref()), &init),
+        )?;
+        resolve(call_method(
+            &self.inner,
+            "put",
+            &Array::of2(&JsValue::from_str(key), &js_resp),
+        )?)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<bool, Error> {
+        let val = resolve(call_method(
+            &self.inner,
+            "delete",
+            &Array::of1(&JsValue::from_str(key)),
+        )?)
+        .await?;
+        Ok(val.as_bool().unwrap_or(false))
+    }
+}
+
+/// In-memory [`CacheStore`], for testing handlers natively, without the Workers runtime.
+/// Entries do not expire.
+#[derive(Debug, Default)]
+pub struct MemoryCache {
+    entries: RefCell<HashMap<String, CachedResponse>>,
+}
+
+impl MemoryCache {
+    /// Returns the number of cached responses
+    pub fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    /// Returns true if the cache is empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.borrow().is_empty()
+    }
+}
+
+#[async_trait(?Send)]
+impl CacheStore for MemoryCache {
+    async fn get(&self, key: &str) -> Result<Option<CachedResponse>, Error> {
+        Ok(self.entries.borrow().get(key).cloned())
+    }
+
+    async fn put(&self, key: &str, response: CachedResponse) -> Result<(), Error> {
+        self.entries.borrow_mut().insert(key.to_string(), response);
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<bool, Error> {
+        Ok(self.entries.borrow_mut().remove(key).is_some())
+    }
+}
+
+/// Middleware that caches responses at the edge.
+///
+/// Before the handlers run, the cache is checked for the request's cache key,
+/// and if there is a cached response, it is returned without invoking the handlers.
+/// On a miss, after the handlers run, a 200 response is stored if its Cache-Control header
+/// allows shared caching with a lifetime (`s-maxage` or `max-age`), and it does not set cookies.
+/// Because the cache key doesn't include request headers, responses that have a
+/// `Content-Encoding`, or that vary on headers other than `Accept-Encoding`, are not stored.
+/// With [`Compression`](crate::Compression), the uncompressed response is stored, and
+/// responses served from the cache are compressed for each client, if `EdgeCache`
+/// is listed before `Compression`.
+/// The response is stored in a deferred task, after it has been returned to the client.
+/// Responses have the [`CACHE_STATUS_HEADER`] header set to "HIT" or "MISS".
+///
+/// The default cache key is the request url, for GET requests without `Range`
+/// or `Authorization` headers; other requests are not cached.
+/// Use [`with_key`](EdgeCache::with_key) to customize the key, for example to ignore
+/// some query parameters. Entries can be removed with [`purge`](EdgeCache::purge),
+/// or from the store directly.
+///
+///```rust,ignore
+/// let cache = EdgeCache::new(Rc::new(WorkersCache::default_cache()?));
+/// let config = ServiceConfig {
+///     middleware: vec![Box::new(cache)],
+///     ..Default::default()
+/// };
+///```
+pub struct EdgeCache {
+    store: Rc<dyn CacheStore>,
+    key_fn: CacheKeyFn,
+}
+
+type CacheKeyFn = Box<dyn Fn(&Request) -> Option<String>>;
+
+impl EdgeCache {
+    /// Creates edge cache middleware using the store
+    pub fn new(store: Rc<dyn CacheStore>) -> Self {
+        EdgeCache {
+            store,
+            key_fn: Box::new(default_cache_key),
+        }
+    }
+
+    /// Sets the function that computes the cache key for a request.
+    /// Requests for which the function returns None are not cached.
+    /// For [`WorkersCache`], keys must be absolute urls.
+    pub fn with_key<F: Fn(&Request) -> Option<String> + 'static>(mut self, key_fn: F) -> Self {
+        self.key_fn = Box::new(key_fn);
+        self
+    }
+
+    /// Returns the cache key for the request, or None if the request should not be cached
+    pub fn cache_key(&self, req: &Request) -> Option<String> {
+        (self.key_fn)(req)
+    }
+
+    /// Removes the response with the key from the cache. Returns true if it was cached.
+    pub async fn purge(&self, key: &str) -> Result<bool, Error> {
+        self.store.delete(key).await
+    }
+}
+
+/// Default cache key: the url of GET requests that have no Range or Authorization header
+fn default_cache_key(req: &Request) -> Option<String> {
+    if req.method() != Method::GET || req.has_header("range") || req.has_header("authorization") {
+        return None;
+    }
+    let mut url = req.url().clone();
+    url.set_fragment(None);
+    Some(url.to_string())
+}
+
+/// Returns true if the response may be stored in a shared cache under a key that
+/// doesn't depend on request headers
+fn is_storable(
+    status: u16,
+    cache_control: Option<&str>,
+    has_cookie: bool,
+    is_encoded: bool,
+    vary: Option<&str>,
+) -> bool {
+    status == 200
+        && !has_cookie
+        && !is_encoded
+        // an unencoded body is acceptable to clients regardless of their Accept-Encoding
+        && vary
+            .map(|vary| {
+                vary.split(',')
+                    .map(|name| name.trim())
+                    .all(|name| name.is_empty() || name.eq_ignore_ascii_case("accept-encoding"))
+            })
+            .unwrap_or(true)
+        && cache_control
+            .map(|cc| CacheControl::parse(cc).shared_ttl().is_some())
+            .unwrap_or(false)
+}
+
+#[async_trait(?Send)]
+impl Middleware for EdgeCache {
+    async fn before(&self, req: &Request, ctx: &mut Context) -> Result<(), HandlerReturn> {
+        let key = match self.cache_key(req) {
+            Some(key) => key,
+            None => return Ok(()),
+        };
+        match self.store.get(&key).await {
+            Ok(Some(cached)) => {
+                if let Err(e) = cached.apply(ctx.response()) {
+                    log!(ctx, Severity::Warning, _:"edge_cache", key: &key, error: e);
+                } else {
+                    // unwrap ok because header value is ascii
+                    ctx.response().header(CACHE_STATUS_HEADER, "HIT").unwrap();
+                }
+            }
+            Ok(None) => {}
+            Err(e) => log!(ctx, Severity::Warning, _:"edge_cache", key: &key, error: e),
+        }
+        Ok(())
+    }
+
+    async fn after(&self, req: &Request, ctx: &mut Context) {
+        let resp = ctx.response();
+        // responses to HEAD requests may not have the full body
+        if req.is_head() || resp.get_header(CACHE_STATUS_HEADER).is_some() {
+            return;
+        }
+        let key = match self.cache_key(req) {
+            Some(key) => key,
+            None => return,
+        };
+        if !is_storable(
+            resp.get_status(),
+            resp.get_header("cache-control").as_deref(),
+            resp.get_header("set-cookie").is_some(),
+            resp.get_header("content-encoding").is_some(),
+            resp.get_header("vary").as_deref(),
+        ) {
+            return;
+        }
+        let entry = match CachedResponse::from_response(resp) {
+            Some(entry) => entry,
+            None => return,
+        };
+        resp.header(CACHE_STATUS_HEADER, "MISS").unwrap();
+        let store = self.store.clone();
+        ctx.defer_local(async move {
+            if let Err(e) = store.put(&key, entry).await {
+                web_sys::console::log_1(&JsValue::from_str(&format!(
+                    "edge cache put failed key({}): {}",
+                    key, e
+                )));
+            }
+        });
+    }
+}
+
+#[test]
+fn storable_responses() {
+    let storable = |status, cc, cookie| is_storable(status, cc, cookie, false, None);
+    assert!(storable(200, Some("public, max-age=60"), false));
+    assert!(storable(200, Some("s-maxage=60"), false));
+    assert!(!storable(200, Some("public, max-age=60"), true));
+    assert!(!storable(404, Some("public, max-age=60"), false));
+    assert!(!storable(200, None, false));
+    assert!(!storable(200, Some("max-age=0"), false));
+    assert!(!storable(200, Some("private, max-age=60"), false));
+    assert!(!storable(200, Some("no-store"), false));
+    assert!(!storable(200, Some("no-cache, max-age=60"), false));
+
+    let cc = Some("max-age=60");
+    assert!(!is_storable(200, cc, false, true, Some("Accept-Encoding")));
+    assert!(is_storable(200, cc, false, false, Some("Accept-Encoding")));
+    assert!(is_storable(
+        200,
+        cc,
+        false,
+        false,
+        Some("accept-encoding, ")
+    ));
+    assert!(!is_storable(
+        200,
+        cc,
+        false,
+        false,
+        Some("Accept-Encoding, Cookie")
+    ));
+    assert!(!is_storable(200, cc, false, false, Some("*")));
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod test {
+    use super::*;
+    use crate::Url;
+    use wasm_bindgen_test::*;
+
+    fn request(method: Method, url: &str) -> Request {
+        Request::new(
+            method,
+            Url::parse(url).unwrap(),
+            web_sys::Headers::new().unwrap(),
+            None,
+        )
+    }
+
+    /// Runs the middleware around a handler that returns the status and cache-control,
+    /// then runs the deferred tasks. Returns the response.
+    async fn run(cache: &EdgeCache, req: &Request, status: u16, cache_control: &str) -> Response {
+        let mut ctx = Context::default();
+        cache.before(req, &mut ctx).await.unwrap();
+        if ctx.response().is_unset() {
+            ctx.response()
+                .header("cache-control", cache_control)
+                .unwrap()
+                .status(status)
+                .text("hello");
+        }
+        cache.after(req, &mut ctx).await;
+        for task in ctx.take_local_tasks() {
+            task.await;
+        }
+        ctx.take_response()
+    }
+
+    #[wasm_bindgen_test]
+    async fn miss_then_hit() {
+        let store = Rc::new(MemoryCache::default());
+        let cache = EdgeCache::new(store.clone());
+        let req = request(Method::GET, "https://example.com/a");
+
+        let resp = run(&cache, &req, 200, "public, max-age=60").await;
+        assert_eq!(
+            resp.get_header(CACHE_STATUS_HEADER).as_deref(),
+            Some("MISS")
+        );
+        assert_eq!(store.len(), 1);
+
+        // the handler would return 500, but the cached response is used
+        let resp = run(&cache, &req, 500, "no-store").await;
+        assert_eq!(resp.get_header(CACHE_STATUS_HEADER).as_deref(), Some("HIT"));
+        assert_eq!(resp.get_status(), 200);
+        assert_eq!(resp.get_body(), b"hello");
+        assert_eq!(store.len(), 1);
+
+        assert!(cache.purge("https://example.com/a").await.unwrap());
+        let resp = run(&cache, &req, 200, "public, max-age=60").await;
+        assert_eq!(
+            resp.get_header(CACHE_STATUS_HEADER).as_deref(),
+            Some("MISS")
+        );
+    }
+
+    #[wasm_bindgen_test]
+    async fn skips_uncacheable() {
+        let store = Rc::new(MemoryCache::default());
+        let cache = EdgeCache::new(store.clone());
+        let req = request(Method::GET, "https://example.com/a");
+
+        let resp = run(&cache, &req, 404, "public, max-age=60").await;
+        assert_eq!(resp.get_header(CACHE_STATUS_HEADER), None);
+        let resp = run(&cache, &req, 200, "no-store").await;
+        assert_eq!(resp.get_header(CACHE_STATUS_HEADER), None);
+        let resp = run(&cache, &req, 200, "private, max-age=60").await;
+        assert_eq!(resp.get_header(CACHE_STATUS_HEADER), None);
+
+        let post = request(Method::POST, "https://example.com/a");
+        let resp = run(&cache, &post, 200, "public, max-age=60").await;
+        assert_eq!(resp.get_header(CACHE_STATUS_HEADER), None);
+        assert!(store.is_empty());
+    }
+
+    #[cfg(feature = "compression")]
+    #[wasm_bindgen_test]
+    async fn compressed_responses() {
+        use crate::Compression;
+
+        /// Runs [EdgeCache, Compression] around a handler; returns the response
+        async fn run(cache: &EdgeCache, accept_encoding: Option<&str>) -> Response {
+            let mut req = request(Method::GET, "https://example.com/a.txt");
+            if let Some(accept) = accept_encoding {
+                let headers = web_sys::Headers::new().unwrap();
+                headers.set("accept-encoding", accept).unwrap();
+                req = Request::new(Method::GET, req.url().clone(), headers, None);
+            }
+            let compression = Compression::default();
+            let mut ctx = Context::default();
+            cache.before(&req, &mut ctx).await.unwrap();
+            if ctx.response().is_unset() {
+                ctx.response()
+                    .header("cache-control", "public, max-age=60")
+                    .unwrap()
+                    .text("hello ".repeat(500));
+            }
+            // after hooks run in reverse order
+            compression.after(&req, &mut ctx).await;
+            cache.after(&req, &mut ctx).await;
+            for task in ctx.take_local_tasks() {
+                task.await;
+            }
+            ctx.take_response()
+        }
+
+        let store = Rc::new(MemoryCache::default());
+        let cache = EdgeCache::new(store.clone());
+
+        // compressed response is not stored
+        let resp = run(&cache, Some("gzip")).await;
+        assert_eq!(resp.get_header("content-encoding").as_deref(), Some("gzip"));
+        assert_eq!(resp.get_header(CACHE_STATUS_HEADER), None);
+        assert!(store.is_empty());
+
+        // uncompressed response is stored
+        let resp = run(&cache, None).await;
+        assert_eq!(resp.get_header("content-encoding"), None);
+        assert_eq!(
+            resp.get_header(CACHE_STATUS_HEADER).as_deref(),
+            Some("MISS")
+        );
+        assert_eq!(store.len(), 1);
+
+        // cached response is compressed only for clients that accept it
+        let resp = run(&cache, Some("gzip")).await;
+        assert_eq!(resp.get_header(CACHE_STATUS_HEADER).as_deref(), Some("HIT"));
+        assert_eq!(resp.get_header("content-encoding").as_deref(), Some("gzip"));
+        let resp = run(&cache, None).await;
+        assert_eq!(resp.get_header(CACHE_STATUS_HEADER).as_deref(), Some("HIT"));
+        assert_eq!(resp.get_header("content-encoding"), None);
+        assert_eq!(resp.get_body(), "hello ".repeat(500).as_bytes());
+    }
+}
@@ -0,0 +1,59 @@
+use crate::js_values;
+use crate::{DurableObjectNamespace, Error, KvNamespace};
+use wasm_bindgen::JsValue;
+
+/// Worker environment bindings: plain-text variables, secrets, KV namespaces, and Durable Objects.
+/// The environment is passed from javascript in the "env" field of the request map.
+/// For Workers using the service-worker syntax, bindings are globals, so the
+/// javascript shim can pass `globalThis`; for module syntax, pass the `env` parameter.
+#[derive(Clone, Debug, Default)]
+pub struct Env {
+    inner: Option<JsValue>,
+}
+
+impl Env {
+    /// Returns the raw javascript value of the binding, or None if it is not defined
+    pub fn binding(&self, name: &str) -> Option<JsValue> {
+        self.inner
+            .as_ref()
+            .and_then(|env| js_values::get_prop(env, name))
+    }
+
+    /// Returns the value of a plain-text environment variable,
+    /// or None if the variable is not defined or is not a string
+    pub fn var(&self, name: &str) -> Option<String> {
+        self.binding(name).and_then(|v| v.as_string())
+    }
+
+    /// Returns the value of a secret. Secrets are stored encrypted by Cloudflare,
+    /// but are visible to the worker as strings, so this is equivalent to [`var`](Env::var).
+    pub fn secret(&self, name: &str) -> Option<String> {
+        self.var(name)
+    }
+
+    /// Returns a handle to the KV namespace bound to the name `binding`
+    pub fn kv(&self, binding: &str) -> Result<KvNamespace, Error> {
+        self.binding(binding)
+            .map(KvNamespace::from)
+            .ok_or_else(|| Error::MissingBinding(binding.to_string()))
+    }
+
+    /// Returns the Durable Object namespace bound to the name `binding`
+    pub fn durable_object(&self, binding: &str) -> Result<DurableObjectNamespace, Error> {
+        self.binding(binding)
+            .map(DurableObjectNamespace::from)
+            .ok_or_else(|| Error::MissingBinding(binding.to_string()))
+    }
+}
+
+impl From<JsValue> for Env {
+    fn from(val: JsValue) -> Env {
+        Env {
+            inner: if val.is_undefined() || val.is_null() {
+                None
+            } else {
+                Some(val)
+            },
+        }
+    }
+}
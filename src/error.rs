@@ -19,6 +19,9 @@ pub enum Error {
     /// Error deserializing asset index
     DeserializeAssets(Box<bincode::ErrorKind>),
 
+    /// Error serializing asset index
+    SerializeAssets(Box<bincode::ErrorKind>),
+
     /// Invalid header value (contains non-ascii characters)
     InvalidHeaderValue(String),
 
@@ -33,6 +36,21 @@ pub enum Error {
     #[allow(clippy::upper_case_acronyms)]
     KVApi(reqwest::Error),
 
+    /// Environment binding (var, secret, or namespace) is not defined
+    MissingBinding(String),
+
+    /// Request body exceeds the maximum body size (the limit, in bytes)
+    PayloadTooLarge(u64),
+
+    /// Request body has a Content-Encoding that is not supported
+    UnsupportedEncoding(String),
+
+    /// Request body could not be decompressed
+    Decompress(String),
+
+    /// Error reading or writing local files (native only)
+    Io(String),
+
     /// Catch-all
     Other(String),
 }
@@ -58,6 +76,15 @@ impl From<reqwest::Error> for Error {
     }
 }
 
+impl From<kv_assets::Error> for Error {
+    fn from(e: kv_assets::Error) -> Self {
+        match e {
+            kv_assets::Error::KVKeyNotFound(key, status) => Error::KVKeyNotFound(key, status),
+            _ => Error::Other(e.to_string()),
+        }
+    }
+}
+
 impl From<JsValue> for Error {
     fn from(e: JsValue) -> Self {
         Error::Js(
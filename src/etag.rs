@@ -0,0 +1,251 @@
+use crate::{handler_return, Context, HandlerReturn, HttpDate, Method, Middleware, Request};
+use async_trait::async_trait;
+use std::str::FromStr;
+
+/// Middleware that adds a strong `ETag` to successful GET and HEAD responses,
+/// and answers conditional requests (`If-None-Match`, `If-Modified-Since`)
+/// with 304 Not Modified.
+///
+/// If the handler sets an `ETag` header, that value is used; otherwise the
+/// ETag is computed from a hash of the response body. Stream bodies are not hashed,
+/// so they only get an ETag if the handler provides one.
+///
+/// The check runs after the handler, so the response is still generated;
+/// it saves bandwidth, not computation. Handlers that can determine the current version
+/// of a resource cheaply should call [`check_preconditions`] before doing the work.
+///
+/// Requests with other methods, such as PUT and DELETE, are not checked by the middleware:
+/// only the handler knows the current state of the resource, so `If-Match` and
+/// `If-Unmodified-Since` are evaluated only if the handler calls [`check_preconditions`]
+/// before modifying the resource.
+///
+/// When used with [`Compression`](crate::Compression), list `ETag` after it,
+/// so that the ETag is computed from the uncompressed body. When `Compression`
+/// encodes the body, it changes the ETag to a weak tag (`W/"..."`), because the
+/// encoded bytes are not the ones the tag was computed from.
+#[derive(Debug, Default)]
+pub struct ETag {}
+
+#[async_trait(?Send)]
+impl Middleware for ETag {
+    async fn after(&self, req: &Request, ctx: &mut Context) {
+        if req.method() != Method::GET && req.method() != Method::HEAD {
+            return;
+        }
+        let resp = ctx.response();
+        if resp.get_status() != 200 {
+            return;
+        }
+        let etag = match resp.get_header("etag") {
+            Some(etag) => etag,
+            // the body of a stream, or of a HEAD response with Content-Length set by the handler,
+            // may not be the full body, so it can't be hashed
+            None if resp.is_stream()
+                || (req.is_head() && resp.get_header("content-length").is_some()) =>
+            {
+                return
+            }
+            None => {
+                let etag = etag_for(resp.get_body());
+                // unwrap ok because etag is ascii
+                resp.header("etag", &etag).unwrap();
+                etag
+            }
+        };
+        let last_modified = resp
+            .get_header("last-modified")
+            .and_then(|v| HttpDate::from_str(&v).ok());
+        if let Err(status) = evaluate(&Conditions::from(req), Some(&etag), last_modified) {
+            resp.status(status).body(Vec::new());
+        }
+    }
+}
+
+/// Returns a strong ETag for the data, including the surrounding quotes.
+/// The tag is derived from the length and a 64-bit FNV-1a hash of the data.
+pub fn etag_for(data: &[u8]) -> String {
+    let hash = data.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("\"{:x}-{:016x}\"", data.len(), hash)
+}
+
+/// Returns the etag as a weak tag, adding the `W/` prefix if it isn't already weak
+#[cfg(feature = "compression")]
+pub(crate) fn weak_etag(etag: &str) -> String {
+    let etag = etag.trim();
+    if etag.starts_with("W/") {
+        etag.to_string()
+    } else {
+        format!("W/{}", etag)
+    }
+}
+
+/// Evaluates the request's precondition headers against the current state of the resource,
+/// following the order in RFC 7232 section 6.
+/// `etag` is the current ETag of the resource (with quotes), or None if the resource
+/// does not exist or has no ETag; `last_modified` is its last modification time, if known.
+///
+/// Returns Err with 412 Precondition Failed if `If-Match` or `If-Unmodified-Since` fail,
+/// or if `If-None-Match` matches on a method other than GET or HEAD (such as a PUT with
+/// `If-None-Match: *` for a resource that exists). For GET and HEAD, returns Err with
+/// 304 Not Modified if `If-None-Match` or `If-Modified-Since` indicate that the client's
+/// copy is current. Otherwise returns Ok, and the request should be processed.
+///
+///```rust,ignore
+/// // optimistic concurrency: update only if the client has the current version
+/// let current = load_doc(&key).await?;
+/// check_preconditions(req, Some(&etag_for(&current)), None)?;
+/// save_doc(&key, req.body()).await?;
+///```
+pub fn check_preconditions(
+    req: &Request,
+    etag: Option<&str>,
+    last_modified: Option<HttpDate>,
+) -> Result<(), HandlerReturn> {
+    evaluate(&Conditions::from(req), etag, last_modified).map_err(|status| match status {
+        304 => handler_return(304, ""),
+        _ => handler_return(412, "Precondition Failed"),
+    })
+}
+
+/// Precondition headers of a request
+#[derive(Default)]
+struct Conditions {
+    is_get: bool,
+    if_match: Option<String>,
+    if_unmodified_since: Option<HttpDate>,
+    if_none_match: Option<String>,
+    if_modified_since: Option<HttpDate>,
+}
+
+impl From<&Request> for Conditions {
+    fn from(req: &Request) -> Conditions {
+        let date = |name: &str| {
+            req.get_header(name)
+                .and_then(|v| HttpDate::from_str(&v).ok())
+        };
+        Conditions {
+            is_get: req.method() == Method::GET || req.method() == Method::HEAD,
+            if_match: req.get_header("if-match"),
+            if_unmodified_since: date("if-unmodified-since"),
+            if_none_match: req.get_header("if-none-match"),
+            if_modified_since: date("if-modified-since"),
+        }
+    }
+}
+
+/// Returns Err(status) if a precondition failed (412) or the client's copy is current (304)
+fn evaluate(
+    cond: &Conditions,
+    etag: Option<&str>,
+    last_modified: Option<HttpDate>,
+) -> Result<(), u16> {
+    match (&cond.if_match, cond.if_unmodified_since, last_modified) {
+        (Some(list), _, _) if !etag_matches(list, etag, false) => return Err(412),
+        (None, Some(since), Some(modified)) if modified > since => return Err(412),
+        _ => {}
+    }
+    match (&cond.if_none_match, cond.if_modified_since, last_modified) {
+        (Some(list), _, _) if etag_matches(list, etag, true) => {
+            return Err(if cond.is_get { 304 } else { 412 })
+        }
+        (None, Some(since), Some(modified)) if cond.is_get && modified <= since => return Err(304),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Returns true if the header value (a list of etags, or "*") matches the etag.
+/// Weak comparison ignores the `W/` prefix; strong comparison never matches weak tags.
+/// "*" matches any current etag.
+fn etag_matches(list: &str, etag: Option<&str>, weak: bool) -> bool {
+    let etag = match etag {
+        Some(etag) => etag,
+        None => return false,
+    };
+    if list.trim() == "*" {
+        return true;
+    }
+    let strip = |tag: &str| -> Option<String> {
+        let tag = tag.trim();
+        match tag.strip_prefix("W/") {
+            Some(t) if weak => Some(t.to_string()),
+            Some(_) => None,
+            None => Some(tag.to_string()),
+        }
+    };
+    match strip(etag) {
+        Some(current) => list.split(',').any(|t| strip(t).as_ref() == Some(&current)),
+        None => false,
+    }
+}
+
+#[test]
+fn etag_hash() {
+    assert_eq!(etag_for(b""), "\"0-cbf29ce484222325\"");
+    assert_eq!(etag_for(b"hello"), etag_for(b"hello"));
+    assert_ne!(etag_for(b"hello"), etag_for(b"hellp"));
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn etag_weak() {
+    assert_eq!(weak_etag("\"abc\""), "W/\"abc\"");
+    assert_eq!(weak_etag("W/\"abc\""), "W/\"abc\"");
+}
+
+#[test]
+fn etag_compare() {
+    let tag = Some("\"abc\"");
+    assert!(etag_matches("\"abc\"", tag, false));
+    assert!(etag_matches("\"x\", \"abc\"", tag, false));
+    assert!(etag_matches("W/\"abc\"", tag, true));
+    assert!(!etag_matches("W/\"abc\"", tag, false));
+    assert!(!etag_matches("\"abc\"", Some("W/\"abc\""), false));
+    assert!(etag_matches("*", tag, false));
+    assert!(!etag_matches("*", None, false));
+    assert!(!etag_matches("\"xyz\"", tag, true));
+}
+
+#[test]
+fn evaluate_conditions() {
+    let tag = Some("\"v2\"");
+    let t1 = HttpDate::from(1000u64);
+    let t2 = HttpDate::from(2000u64);
+
+    let get = |if_none_match: Option<&str>, if_modified_since| Conditions {
+        is_get: true,
+        if_none_match: if_none_match.map(String::from),
+        if_modified_since,
+        ..Default::default()
+    };
+    assert_eq!(evaluate(&get(Some("\"v2\""), None), tag, None), Err(304));
+    assert_eq!(evaluate(&get(Some("\"v1\""), None), tag, None), Ok(()));
+    assert_eq!(evaluate(&get(None, Some(t2)), tag, Some(t1)), Err(304));
+    assert_eq!(evaluate(&get(None, Some(t1)), tag, Some(t2)), Ok(()));
+    // If-None-Match takes precedence over If-Modified-Since
+    assert_eq!(
+        evaluate(&get(Some("\"v1\""), Some(t2)), tag, Some(t1)),
+        Ok(())
+    );
+
+    let put = |if_match: Option<&str>, if_unmodified_since| Conditions {
+        if_match: if_match.map(String::from),
+        if_unmodified_since,
+        ..Default::default()
+    };
+    assert_eq!(evaluate(&put(Some("\"v2\""), None), tag, None), Ok(()));
+    assert_eq!(evaluate(&put(Some("\"v1\""), None), tag, None), Err(412));
+    assert_eq!(evaluate(&put(Some("*"), None), None, None), Err(412));
+    assert_eq!(evaluate(&put(None, Some(t1)), tag, Some(t2)), Err(412));
+    assert_eq!(evaluate(&put(None, Some(t2)), tag, Some(t1)), Ok(()));
+
+    // create-only PUT
+    let create = Conditions {
+        if_none_match: Some("*".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(evaluate(&create, tag, None), Err(412));
+    assert_eq!(evaluate(&create, None, None), Ok(()));
+}
@@ -0,0 +1,69 @@
+//! Glob pattern matching for url paths
+
+/// Returns true if the path matches the glob pattern.
+/// - `*` matches any sequence of characters except `/`
+/// - `**` matches any sequence of characters, including `/`
+/// - `?` matches any single character except `/`
+///
+/// If the pattern contains no `/`, it is matched against the last segment of the path
+/// (the file name), so `*.js` matches `app.js` and `static/app.js`.
+/// Otherwise, it is matched against the whole path; leading slashes are ignored
+/// on both pattern and path.
+pub(crate) fn glob_match(pattern: &str, path: &str) -> bool {
+    let path = path.trim_start_matches('/');
+    if pattern.contains('/') {
+        matches(pattern.trim_start_matches('/').as_bytes(), path.as_bytes())
+    } else {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        matches(pattern.as_bytes(), name.as_bytes())
+    }
+}
+
+fn matches(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) if rest.first() == Some(&b'*') => {
+            let rest = &rest[1..];
+            // "**/" also matches zero directories
+            if rest.first() == Some(&b'/') && matches(&rest[1..], text) {
+                return true;
+            }
+            (0..=text.len()).any(|i| matches(rest, &text[i..]))
+        }
+        Some((b'*', rest)) => {
+            for i in 0..=text.len() {
+                if matches(rest, &text[i..]) {
+                    return true;
+                }
+                if text.get(i) == Some(&b'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Some((b'?', rest)) => match text.split_first() {
+            Some((c, text)) if *c != b'/' => matches(rest, text),
+            _ => false,
+        },
+        Some((p, rest)) => match text.split_first() {
+            Some((c, text)) if c == p => matches(rest, text),
+            _ => false,
+        },
+    }
+}
+
+#[test]
+fn glob_patterns() {
+    assert!(glob_match("*.js", "/app.js"));
+    assert!(glob_match("*.js", "/static/js/app.js"));
+    assert!(!glob_match("*.js", "/app.json"));
+    assert!(glob_match("app.??.js", "app.v1.js"));
+    assert!(glob_match("/static/*.css", "static/site.css"));
+    assert!(!glob_match("/static/*.css", "static/css/site.css"));
+    assert!(glob_match("/static/**", "/static/css/site.css"));
+    assert!(glob_match("/static/**/*.css", "/static/site.css"));
+    assert!(glob_match("/static/**/*.css", "/static/a/b/site.css"));
+    assert!(!glob_match("/static/**/*.css", "/other/site.css"));
+    assert!(glob_match("*", "index.html"));
+    assert!(glob_match("/", "/"));
+}
@@ -1,16 +1,27 @@
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use std::convert::TryFrom;
 use std::fmt;
+use std::ops::{Add, Sub};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-/// Time in UTC, with conversions to/from u64 and rfc2822
+/// Time in UTC, with one-second resolution, for HTTP date headers
+/// such as Last-Modified and If-Modified-Since.
+/// Formatted as IMF-fixdate ("Sun, 06 Nov 1994 08:49:37 GMT"), and parsed from IMF-fixdate,
+/// the obsolete RFC 850 and asctime formats, or RFC 2822.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct HttpDate(u64);
 
-/// Convert HttpDate to printable string in rfc2822 format
+/// IMF-fixdate, the preferred format (RFC 7231 section 7.1.1.1)
+const IMF_FIXDATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+/// obsolete RFC 850 format
+const RFC850_DATE: &str = "%A, %d-%b-%y %H:%M:%S GMT";
+/// obsolete ANSI C asctime() format
+const ASCTIME_DATE: &str = "%a %b %e %H:%M:%S %Y";
+
+/// Convert HttpDate to printable string in IMF-fixdate format
 impl fmt::Display for HttpDate {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        use chrono::{DateTime, NaiveDateTime, Utc};
-
-        let dt = DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(self.0 as i64, 0), Utc);
-        fmt::Display::fmt(&dt.to_rfc2822(), f)
+        fmt::Display::fmt(&DateTime::<Utc>::from(*self).format(IMF_FIXDATE), f)
     }
 }
 
@@ -21,26 +32,96 @@ impl From<u64> for HttpDate {
     }
 }
 
-/// Convert i64 timestamp (seconds since EPOCH in UTC) to HttpDate
+/// Convert i64 timestamp (seconds since EPOCH in UTC) to HttpDate.
+/// Times before the EPOCH are converted to the EPOCH.
 impl From<i64> for HttpDate {
     fn from(utc_sec: i64) -> HttpDate {
-        HttpDate(utc_sec as u64)
+        HttpDate(utc_sec.max(0) as u64)
+    }
+}
+
+/// Convert SystemTime to HttpDate, truncating to seconds.
+/// Times before the EPOCH are converted to the EPOCH.
+impl From<SystemTime> for HttpDate {
+    fn from(time: SystemTime) -> HttpDate {
+        HttpDate(
+            time.duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        )
+    }
+}
+
+/// Convert HttpDate to SystemTime
+impl From<HttpDate> for SystemTime {
+    fn from(date: HttpDate) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(date.0)
+    }
+}
+
+/// Convert chrono DateTime to HttpDate, truncating to seconds.
+/// Times before the EPOCH are converted to the EPOCH.
+impl<Tz: TimeZone> From<DateTime<Tz>> for HttpDate {
+    fn from(dt: DateTime<Tz>) -> HttpDate {
+        HttpDate::from(dt.timestamp())
+    }
+}
+
+/// Convert HttpDate to chrono DateTime.
+/// Dates past the range of DateTime (about 262,000 years) are converted to the maximum.
+impl From<HttpDate> for DateTime<Utc> {
+    fn from(date: HttpDate) -> DateTime<Utc> {
+        i64::try_from(date.0)
+            .ok()
+            .and_then(|secs| Utc.timestamp_opt(secs, 0).single())
+            .unwrap_or_else(|| Utc.from_utc_datetime(&NaiveDateTime::MAX))
     }
 }
 
 impl std::str::FromStr for HttpDate {
     type Err = chrono::format::ParseError;
 
-    /// Parse string to HttpDate
+    /// Parse string to HttpDate. Accepts IMF-fixdate, RFC 850, asctime, and RFC 2822 formats.
     fn from_str(s: &str) -> Result<HttpDate, Self::Err> {
-        let utc_sec = chrono::DateTime::parse_from_rfc2822(s).map(|dt| dt.timestamp() as u64)?;
-        Ok(HttpDate(utc_sec))
+        let s = s.trim();
+        let parsed = NaiveDateTime::parse_from_str(s, IMF_FIXDATE)
+            .or_else(|_| NaiveDateTime::parse_from_str(s, RFC850_DATE))
+            .or_else(|_| NaiveDateTime::parse_from_str(s, ASCTIME_DATE))
+            .map(|ndt| Utc.from_utc_datetime(&ndt).timestamp());
+        match parsed {
+            Ok(utc_sec) => Ok(HttpDate::from(utc_sec)),
+            Err(_) => DateTime::parse_from_rfc2822(s).map(HttpDate::from),
+        }
+    }
+}
+
+/// Adds the duration, truncated to seconds
+impl Add<Duration> for HttpDate {
+    type Output = HttpDate;
+
+    fn add(self, rhs: Duration) -> HttpDate {
+        HttpDate(self.0.saturating_add(rhs.as_secs()))
+    }
+}
+
+/// Subtracts the duration, truncated to seconds. The result is not earlier than the EPOCH.
+impl Sub<Duration> for HttpDate {
+    type Output = HttpDate;
+
+    fn sub(self, rhs: Duration) -> HttpDate {
+        HttpDate(self.0.saturating_sub(rhs.as_secs()))
     }
 }
 
 impl HttpDate {
-    /// Convert HttpDate to i64 timestamp (seconds since EPOCH in UTC)
+    /// Convert HttpDate to u64 timestamp (seconds since EPOCH in UTC)
     pub fn timestamp(&self) -> u64 {
         self.0
     }
+
+    /// Returns the time elapsed from the earlier date to this one,
+    /// or None if the earlier date is later than this one
+    pub fn duration_since(&self, earlier: HttpDate) -> Option<Duration> {
+        self.0.checked_sub(earlier.0).map(Duration::from_secs)
+    }
 }
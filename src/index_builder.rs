@@ -0,0 +1,236 @@
+use crate::glob::glob_match;
+use crate::Error;
+use kv_assets::{AssetIndex, AssetMetadata};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Scans a folder of static assets, and generates the [`AssetIndex`] used by
+/// [`StaticAssetHandler`](crate::StaticAssetHandler), and an [`UploadManifest`]
+/// listing the KV keys and files to upload.
+///
+/// The KV key of each asset contains the sha256 hash of its content
+/// (for example, `css/site.<hash>.css`), so a changed file gets a new key,
+/// and unchanged files don't need to be uploaded again.
+/// Hidden files (names beginning with '.') are skipped, except in `.well-known`.
+///
+///```no_run
+/// # use wasm_service::IndexBuilder;
+/// # fn main() -> Result<(), wasm_service::Error> {
+/// let (index, manifest) = IndexBuilder::new("public").exclude("*.map").build()?;
+/// std::fs::write("data/assets.bin", IndexBuilder::serialize(&index)?).unwrap();
+/// std::fs::write("data/manifest.json", manifest.to_json()?).unwrap();
+/// # Ok(())
+/// # }
+///```
+#[derive(Debug)]
+pub struct IndexBuilder {
+    root: PathBuf,
+    exclude: Vec<String>,
+}
+
+/// Asset to be uploaded to KV
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UploadEntry {
+    /// KV key
+    pub key: String,
+    /// asset path (url path without leading '/')
+    pub path: String,
+    /// local file containing the asset
+    pub file: PathBuf,
+    /// size, in bytes
+    pub size: u64,
+    /// last-modified time, in seconds since the epoch (UTC)
+    pub modified: u64,
+    /// media type, from the file extension
+    pub media_type: Option<String>,
+    /// sha256 hash of the content, in hex
+    pub sha256: String,
+}
+
+/// All assets in a folder, with their KV keys
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct UploadManifest {
+    /// assets, sorted by path
+    pub entries: Vec<UploadEntry>,
+}
+
+/// Changes needed to update KV from a previous version of the assets
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ManifestDiff {
+    /// assets whose keys are not in KV
+    pub upload: Vec<UploadEntry>,
+    /// keys in KV that are no longer used, and may be deleted
+    /// after the worker with the new index has been published
+    pub stale: Vec<String>,
+}
+
+impl IndexBuilder {
+    /// Creates builder for the asset folder
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        IndexBuilder {
+            root: root.into(),
+            exclude: Vec::new(),
+        }
+    }
+
+    /// Skips files whose path matches the glob pattern (see
+    /// [`StaticAssetHandler::with_path_cache`](crate::StaticAssetHandler::with_path_cache)
+    /// for the pattern syntax). May be called more than once.
+    pub fn exclude(mut self, glob: &str) -> Self {
+        self.exclude.push(glob.to_string());
+        self
+    }
+
+    /// Scans the folder, reading each file to compute its hash
+    pub fn build(&self) -> Result<(AssetIndex, UploadManifest), Error> {
+        let mut files = Vec::new();
+        self.scan(&self.root, "", &mut files)?;
+        files.sort();
+
+        let mut index = AssetIndex::new();
+        let mut manifest = UploadManifest::default();
+        for (path, file) in files {
+            let data = std::fs::read(&file).map_err(|e| io_error(&file, e))?;
+            let modified = std::fs::metadata(&file)
+                .and_then(|md| md.modified())
+                .map_err(|e| io_error(&file, e))?
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            let sha256 = hex::encode(Sha256::digest(&data));
+            let key = asset_key(&path, &sha256);
+            index.insert(
+                path.clone(),
+                AssetMetadata {
+                    path: key.clone(),
+                    modified,
+                    size: data.len() as u64,
+                },
+            );
+            manifest.entries.push(UploadEntry {
+                key,
+                media_type: crate::media_type(&path).map(|mt| mt.to_string()),
+                path,
+                file,
+                size: data.len() as u64,
+                modified,
+                sha256,
+            });
+        }
+        Ok((index, manifest))
+    }
+
+    /// Serializes the index, for [`StaticAssetHandler::init`](crate::StaticAssetHandler::init)
+    /// and the other asset stores
+    pub fn serialize(index: &AssetIndex) -> Result<Vec<u8>, Error> {
+        bincode::serialize(index).map_err(Error::SerializeAssets)
+    }
+
+    /// Adds files in the folder (and subfolders) to the list, as (asset path, file path)
+    fn scan(
+        &self,
+        dir: &Path,
+        prefix: &str,
+        files: &mut Vec<(String, PathBuf)>,
+    ) -> Result<(), Error> {
+        let entries = std::fs::read_dir(dir).map_err(|e| io_error(dir, e))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| io_error(dir, e))?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') && name != ".well-known" {
+                continue;
+            }
+            let path = format!("{}{}", prefix, name);
+            let file_type = entry.file_type().map_err(|e| io_error(&entry.path(), e))?;
+            if file_type.is_dir() {
+                self.scan(&entry.path(), &format!("{}/", path), files)?;
+            } else if file_type.is_file()
+                && !self.exclude.iter().any(|glob| glob_match(glob, &path))
+            {
+                files.push((path, entry.path()));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl UploadManifest {
+    /// Serializes the manifest as json
+    pub fn to_json(&self) -> Result<Vec<u8>, Error> {
+        Ok(serde_json::to_vec_pretty(self)?)
+    }
+
+    /// Reads manifest from json
+    pub fn from_json(data: &[u8]) -> Result<Self, Error> {
+        Ok(serde_json::from_slice(data)?)
+    }
+
+    /// Compares this manifest with the keys already in KV (for example,
+    /// from the manifest of the previous upload), and returns the assets to upload
+    /// and the keys that are no longer needed
+    pub fn diff<I: IntoIterator<Item = String>>(&self, existing_keys: I) -> ManifestDiff {
+        let existing = existing_keys.into_iter().collect::<HashSet<String>>();
+        let current = self
+            .entries
+            .iter()
+            .map(|entry| entry.key.as_str())
+            .collect::<HashSet<&str>>();
+        let mut stale = existing
+            .iter()
+            .filter(|key| !current.contains(key.as_str()))
+            .cloned()
+            .collect::<Vec<String>>();
+        stale.sort();
+        ManifestDiff {
+            upload: self
+                .entries
+                .iter()
+                .filter(|entry| !existing.contains(&entry.key))
+                .cloned()
+                .collect(),
+            stale,
+        }
+    }
+
+    /// Returns the entries as json for `wrangler kv:bulk put`, with base64-encoded values
+    pub fn bulk_json(entries: &[UploadEntry]) -> Result<Vec<u8>, Error> {
+        let mut items = Vec::new();
+        for entry in entries {
+            let data = std::fs::read(&entry.file).map_err(|e| io_error(&entry.file, e))?;
+            items.push(serde_json::json!({
+                "key": entry.key,
+                "value": base64::encode(&data),
+                "base64": true,
+            }));
+        }
+        Ok(serde_json::to_vec(&items)?)
+    }
+}
+
+/// Returns the KV key for the asset: the path, with the hash inserted before the extension
+fn asset_key(path: &str, hash: &str) -> String {
+    let name_start = path.rfind('/').map(|pos| pos + 1).unwrap_or(0);
+    match path[name_start..].rfind('.') {
+        Some(dot) if dot > 0 => {
+            let dot = name_start + dot;
+            format!("{}.{}{}", &path[..dot], hash, &path[dot..])
+        }
+        _ => format!("{}.{}", path, hash),
+    }
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> Error {
+    Error::Io(format!("{}: {}", path.display(), e))
+}
+
+#[test]
+fn asset_keys() {
+    assert_eq!(asset_key("css/site.css", "abc"), "css/site.abc.css");
+    assert_eq!(asset_key("app.min.js", "abc"), "app.min.abc.js");
+    assert_eq!(asset_key("LICENSE", "abc"), "LICENSE.abc");
+    assert_eq!(asset_key("a.b/LICENSE", "abc"), "a.b/LICENSE.abc");
+    assert_eq!(asset_key(".well-known/.env", "abc"), ".well-known/.env.abc");
+}
@@ -12,17 +12,6 @@ pub(crate) fn get_map_str(map: &js_sys::Map, key: &str) -> Option<String> {
     }
 }
 
-/// Retrieve Vec<u8> from map
-pub(crate) fn get_map_bytes(map: &js_sys::Map, key: &str) -> Option<Vec<u8>> {
-    let val = map.get(&JsValue::from_str(key));
-    if !val.is_undefined() {
-        let arr = js_sys::Uint8Array::from(val);
-        Some(arr.to_vec())
-    } else {
-        None
-    }
-}
-
 /// Retrieve headers from map
 pub(crate) fn get_map_headers(map: &js_sys::Map, key: &str) -> Option<web_sys::Headers> {
     let val = map.get(&JsValue::from_str(key));
@@ -32,3 +21,103 @@ pub(crate) fn get_map_headers(map: &js_sys::Map, key: &str) -> Option<web_sys::H
         None
     }
 }
+
+/// Retrieve a property of a javascript object, or None if the property is undefined
+pub(crate) fn get_prop(obj: &JsValue, key: &str) -> Option<JsValue> {
+    match js_sys::Reflect::get(obj, &JsValue::from_str(key)) {
+        Ok(val) if !val.is_undefined() => Some(val),
+        _ => None,
+    }
+}
+
+/// Invoke a method on a javascript object
+pub(crate) fn call_method(
+    obj: &JsValue,
+    name: &str,
+    args: &js_sys::Array,
+) -> Result<JsValue, crate::Error> {
+    let func = js_sys::Function::from(
+        get_prop(obj, name)
+            .ok_or_else(|| crate::Error::Js(format!("missing javascript method '{}'", name)))?,
+    );
+    Ok(func.apply(obj, args)?)
+}
+
+/// Wait for a javascript Promise to resolve
+pub(crate) async fn resolve(promise: JsValue) -> Result<JsValue, crate::Error> {
+    Ok(wasm_bindgen_futures::JsFuture::from(js_sys::Promise::from(promise)).await?)
+}
+
+/// Set a property on a javascript object
+pub(crate) fn set_prop(obj: &JsValue, key: &str, val: JsValue) -> Result<(), crate::Error> {
+    js_sys::Reflect::set(obj, &JsValue::from_str(key), &val)?;
+    Ok(())
+}
+
+/// Retrieve a numeric value from map
+pub(crate) fn get_map_f64(map: &js_sys::Map, key: &str) -> Option<f64> {
+    map.get(&JsValue::from_str(key)).as_f64()
+}
+
+/// Future that completes after a delay, using the javascript setTimeout function.
+/// If it is dropped before completing, the timer is cancelled with clearTimeout.
+pub(crate) struct Sleep {
+    timer: JsValue,
+    future: wasm_bindgen_futures::JsFuture,
+}
+
+impl Sleep {
+    pub(crate) fn new(delay: std::time::Duration) -> Self {
+        let mut timer = JsValue::UNDEFINED;
+        let promise = js_sys::Promise::new(&mut |resolve, _reject| {
+            if let Ok(id) = call_method(
+                &js_sys::global(),
+                "setTimeout",
+                &js_sys::Array::of2(&resolve, &JsValue::from_f64(delay.as_millis() as f64)),
+            ) {
+                timer = id;
+            }
+        });
+        Sleep {
+            timer,
+            future: wasm_bindgen_futures::JsFuture::from(promise),
+        }
+    }
+}
+
+impl std::future::Future for Sleep {
+    type Output = ();
+
+    fn poll(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<()> {
+        std::pin::Pin::new(&mut self.future).poll(cx).map(|_| ())
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if !self.timer.is_undefined() {
+            let _ = call_method(
+                &js_sys::global(),
+                "clearTimeout",
+                &js_sys::Array::of1(&self.timer),
+            );
+        }
+    }
+}
+
+/// Returns the name/value pairs of javascript Headers
+pub(crate) fn headers_to_vec(headers: &web_sys::Headers) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    if let Ok(Some(iter)) = js_sys::try_iter(headers) {
+        for entry in iter.flatten() {
+            let entry = js_sys::Array::from(&entry);
+            if let (Some(name), Some(val)) = (entry.get(0).as_string(), entry.get(1).as_string()) {
+                pairs.push((name, val));
+            }
+        }
+    }
+    pairs
+}
@@ -0,0 +1,159 @@
+use crate::js_values::{call_method, get_prop, resolve, set_prop};
+use crate::Error;
+use bytes::Bytes;
+use js_sys::{Array, Object, Uint8Array};
+use wasm_bindgen::JsValue;
+
+/// Handle to a Workers KV namespace, obtained from [`Env::kv`](crate::Env::kv).
+/// Operations use the namespace binding in the worker runtime,
+/// so they do not require api credentials or an external http request.
+#[derive(Clone, Debug)]
+pub struct KvNamespace {
+    inner: JsValue,
+}
+
+/// Optional parameters for [`KvNamespace::put`]
+#[derive(Clone, Debug, Default)]
+pub struct KvPutOptions {
+    /// Absolute expiration time, in seconds since EPOCH
+    pub expiration: Option<u64>,
+    /// Expiration, in seconds from now. Must be at least 60.
+    pub expiration_ttl: Option<u64>,
+}
+
+/// Optional parameters for [`KvNamespace::list`]
+#[derive(Clone, Debug, Default)]
+pub struct KvListOptions {
+    /// Only return keys beginning with the prefix
+    pub prefix: Option<String>,
+    /// Maximum number of keys to return (the service maximum is 1000)
+    pub limit: Option<u32>,
+    /// Cursor returned from a previous incomplete list
+    pub cursor: Option<String>,
+}
+
+/// Key returned from [`KvNamespace::list`]
+#[derive(Clone, Debug)]
+pub struct KvKey {
+    /// Name of key
+    pub name: String,
+    /// Expiration time in seconds since EPOCH, if the key has an expiration
+    pub expiration: Option<u64>,
+}
+
+/// Results of [`KvNamespace::list`]
+#[derive(Clone, Debug, Default)]
+pub struct KvList {
+    /// keys in this page of results
+    pub keys: Vec<KvKey>,
+    /// true if there are no more keys
+    pub list_complete: bool,
+    /// if list is not complete, cursor to be passed to the next list call
+    pub cursor: Option<String>,
+}
+
+impl KvNamespace {
+    /// Returns the value for the key, or None if the key was not found
+    pub async fn get(&self, key: &str) -> Result<Option<Bytes>, Error> {
+        let val = self.get_as(key, "arrayBuffer").await?;
+        Ok(val.map(|v| Bytes::from(Uint8Array::new(&v).to_vec())))
+    }
+
+    /// Returns the value for the key as a utf-8 string, or None if the key was not found
+    pub async fn get_text(&self, key: &str) -> Result<Option<String>, Error> {
+        let val = self.get_as(key, "text").await?;
+        Ok(val.and_then(|v| v.as_string()))
+    }
+
+    async fn get_as(&self, key: &str, value_type: &str) -> Result<Option<JsValue>, Error> {
+        let promise = call_method(
+            &self.inner,
+            "get",
+            &Array::of2(&JsValue::from_str(key), &JsValue::from_str(value_type)),
+        )?;
+        let val = resolve(promise).await?;
+        if val.is_null() || val.is_undefined() {
+            Ok(None)
+        } else {
+            Ok(Some(val))
+        }
+    }
+
+    /// Stores the value at the key
+    pub async fn put<T: AsRef<[u8]>>(
+        &self,
+        key: &str,
+        val: T,
+        options: &KvPutOptions,
+    ) -> Result<(), Error> {
+        let opts = Object::new();
+        if let Some(expiration) = options.expiration {
+            set_prop(&opts, "expiration", JsValue::from_f64(expiration as f64))?;
+        }
+        if let Some(ttl) = options.expiration_ttl {
+            set_prop(&opts, "expirationTtl", JsValue::from_f64(ttl as f64))?;
+        }
+        let promise = call_method(
+            &self.inner,
+            "put",
+            &Array::of3(
+                &JsValue::from_str(key),
+                &Uint8Array::from(val.as_ref()),
+                &opts,
+            ),
+        )?;
+        resolve(promise).await?;
+        Ok(())
+    }
+
+    /// Deletes the key. Deleting a key that does not exist is not an error.
+    pub async fn delete(&self, key: &str) -> Result<(), Error> {
+        let promise = call_method(&self.inner, "delete", &Array::of1(&JsValue::from_str(key)))?;
+        resolve(promise).await?;
+        Ok(())
+    }
+
+    /// Lists keys in the namespace, in lexicographic order
+    pub async fn list(&self, options: &KvListOptions) -> Result<KvList, Error> {
+        let opts = Object::new();
+        if let Some(ref prefix) = options.prefix {
+            set_prop(&opts, "prefix", JsValue::from_str(prefix))?;
+        }
+        if let Some(limit) = options.limit {
+            set_prop(&opts, "limit", JsValue::from_f64(limit as f64))?;
+        }
+        if let Some(ref cursor) = options.cursor {
+            set_prop(&opts, "cursor", JsValue::from_str(cursor))?;
+        }
+        let promise = call_method(&self.inner, "list", &Array::of1(&opts))?;
+        let result = resolve(promise).await?;
+        let keys = get_prop(&result, "keys")
+            .map(|keys| {
+                Array::from(&keys)
+                    .iter()
+                    .map(|k| KvKey {
+                        name: get_prop(&k, "name")
+                            .and_then(|v| v.as_string())
+                            .unwrap_or_default(),
+                        expiration: get_prop(&k, "expiration")
+                            .and_then(|v| v.as_f64())
+                            .map(|n| n as u64),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(KvList {
+            keys,
+            list_complete: get_prop(&result, "list_complete")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(true),
+            cursor: get_prop(&result, "cursor").and_then(|v| v.as_string()),
+        })
+    }
+}
+
+impl From<JsValue> for KvNamespace {
+    fn from(inner: JsValue) -> KvNamespace {
+        KvNamespace { inner }
+    }
+}
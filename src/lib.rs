@@ -15,7 +15,7 @@ pub use method::Method;
 mod request;
 pub use request::Request;
 mod response;
-pub use response::{Body, Response};
+pub use response::{Body, BodyStream, Response};
 mod media_type;
 pub use media_type::media_type;
 
@@ -24,11 +24,58 @@ pub use url::Url;
 
 mod context;
 pub use context::Context;
+mod env;
+pub use env::Env;
+mod kv;
+pub use kv::{KvKey, KvList, KvListOptions, KvNamespace, KvPutOptions};
+mod cache_control;
+pub use cache_control::CacheControl;
+mod asset_store;
+#[cfg(not(target_arch = "wasm32"))]
+pub use asset_store::DirAssets;
+pub use asset_store::{AssetStore, KvBindingAssets, KvRestAssets, MemoryAssets};
+mod asset_cache;
+pub use asset_cache::{AssetCache, AssetCacheStats};
+#[cfg(not(target_arch = "wasm32"))]
+mod index_builder;
+#[cfg(not(target_arch = "wasm32"))]
+pub use index_builder::{IndexBuilder, ManifestDiff, UploadEntry, UploadManifest};
 mod assets;
 pub use assets::StaticAssetHandler;
+mod rules;
+/// re-export asset index types from kv_assets
+pub use kv_assets::{AssetIndex, AssetMetadata};
+pub use rules::Rules;
+mod accept_encoding;
+pub(crate) mod glob;
 mod httpdate;
 pub(crate) mod js_values;
 pub use httpdate::HttpDate;
+mod scheduled;
+pub use scheduled::{service_scheduled, ScheduledConfig, ScheduledHandler};
+mod durable;
+pub use durable::{
+    durable_object_alarm, durable_object_fetch, DurableObject, DurableObjectId,
+    DurableObjectNamespace, DurableObjectStub, DurableStorage, StubResponse,
+};
+mod sse;
+pub use sse::{Event, EventSender};
+mod websocket;
+pub use websocket::{WebSocket, WebSocketHandler, WebSocketPair, WsMessage};
+mod queue;
+pub use queue::{service_queue, AckState, MessageBatch, QueueConfig, QueueHandler, QueueMessage};
+mod edge_cache;
+pub use edge_cache::{
+    CacheStore, CachedResponse, EdgeCache, MemoryCache, WorkersCache, CACHE_STATUS_HEADER,
+};
+mod range;
+pub use range::{if_range_matches, parse_range, ByteRange, Ranges};
+mod etag;
+pub use etag::{check_preconditions, etag_for, ETag};
+#[cfg(feature = "compression")]
+mod compress;
+#[cfg(feature = "compression")]
+pub use compress::{Compression, CompressionConfig};
 
 /// Logging support for deferred tasks
 #[derive(Debug)]
@@ -117,6 +164,20 @@ impl Default for HandlerReturn {
     }
 }
 
+/// Converts an Error to a HandlerReturn, so that errors may be returned
+/// from handlers with `?`. [`Error::PayloadTooLarge`] becomes status 413;
+/// other errors become status 500, without revealing error details to the client.
+impl From<Error> for HandlerReturn {
+    fn from(e: Error) -> HandlerReturn {
+        match e {
+            Error::PayloadTooLarge(_) => handler_return(413, "Payload Too Large"),
+            Error::UnsupportedEncoding(_) => handler_return(415, "Unsupported Media Type"),
+            Error::Decompress(_) => handler_return(400, "Bad Request"),
+            _ => handler_return(500, "Internal Server Error"),
+        }
+    }
+}
+
 /// Trait that defines app/service's request handler and router
 /// See [rustwasm-service-template](https://github.com/stevelr/rustwasm-service-template/blob/master/src/lib.rs)
 ///   for a more complete example
@@ -151,6 +212,24 @@ pub trait Handler {
     async fn handle(&self, req: &Request, ctx: &mut Context) -> Result<(), HandlerReturn>;
 }
 
+/// Middleware runs before and after the request handlers, for processing that
+/// applies to all requests, such as compression or caching.
+///
+/// Middleware `before` methods are invoked in order, before the handlers. If a middleware
+/// sets the response (or returns a HandlerReturn), the remaining middleware and the handlers are skipped.
+/// After the response has been generated (by a handler, middleware, or the not-found or internal error handler),
+/// `after` methods are invoked in reverse order, and may modify `ctx.response()`.
+#[async_trait(?Send)]
+pub trait Middleware {
+    /// Invoked before the request handlers. The default implementation does nothing.
+    async fn before(&self, _req: &Request, _ctx: &mut Context) -> Result<(), HandlerReturn> {
+        Ok(())
+    }
+
+    /// Invoked after the response has been generated. The default implementation does nothing.
+    async fn after(&self, _req: &Request, _ctx: &mut Context) {}
+}
+
 /// Configuration parameters for service
 /// Parameter E is your crate's error type
 pub struct ServiceConfig {
@@ -160,6 +239,9 @@ pub struct ServiceConfig {
     /// Request handler
     pub handlers: Vec<Box<dyn Handler>>,
 
+    /// Middleware, run before and after the handlers
+    pub middleware: Vec<Box<dyn Middleware>>,
+
     /// how to handle internal errors. This function should modify ctx.response()
     /// with results, which, for example, could include rendering a page or sending
     /// a redirect. The default implementation returns status 200 with a short text message.
@@ -169,6 +251,19 @@ pub struct ServiceConfig {
     /// with results, which, for example, could include rendering a page or sending
     /// a redirect. The default implementation returns status 404 with a short text message.
     pub not_found_handler: fn(req: &Request, ctx: &mut Context),
+
+    /// Maximum size of request body, in bytes. If the Content-Length of the request
+    /// is larger, the request fails with 413 Payload Too Large before any handler is invoked.
+    /// If a handler reads a streamed body that exceeds the limit, the read fails with
+    /// [`Error::PayloadTooLarge`]. The default is None (no limit).
+    pub max_body_size: Option<u64>,
+
+    /// If true, request bodies with a Content-Encoding of gzip, deflate, or br
+    /// are decompressed before the handlers are invoked, so that `req.body()` and `req.json()`
+    /// return the decoded body. See [`Request::decompress`] for size limits.
+    /// The default is false. Requires the `compression` feature.
+    #[cfg(feature = "compression")]
+    pub decompress_requests: bool,
 }
 
 impl Default for ServiceConfig {
@@ -177,29 +272,88 @@ impl Default for ServiceConfig {
         ServiceConfig {
             logger: service_logging::silent_logger(),
             handlers: Vec::new(),
+            middleware: Vec::new(),
             internal_error_handler: default_internal_error_handler,
             not_found_handler: default_not_found_handler,
+            max_body_size: None,
+            #[cfg(feature = "compression")]
+            decompress_requests: false,
         }
     }
 }
 
 struct DeferredData {
     tasks: Vec<Box<dyn Runnable + std::panic::UnwindSafe>>,
+    local_tasks: Vec<futures::future::LocalBoxFuture<'static, ()>>,
     logs: Vec<LogEntry>,
     logger: Box<dyn Logger>,
+    sub: &'static str,
 }
 
 /// Entrypoint for wasm-service. Converts parameters from javascript into [Request],
 /// invokes app-specific [Handler](trait.Handler.html), and converts [`Response`] to javascript.
 /// Also sends logs to [Logger](https://docs.rs/service-logging/0.3/service_logging/trait.Logger.html) and runs deferred tasks.
 pub async fn service_request(req: JsValue, config: ServiceConfig) -> Result<JsValue, JsValue> {
-    let mut is_err = false;
     let map = js_sys::Map::from(req);
-    let req = Request::from_js(&map)?;
     let mut ctx = Context::default();
-    let mut handler_result = Ok(());
-    for handler in config.handlers.iter() {
+    ctx.set_env(Env::from(map.get(&"env".into())));
+    dispatch_fetch(&map, ctx, None, config).await
+}
+
+/// Processes a fetch request: runs the `first` handler (if any), followed by the
+/// configured handlers, until one of them sets the response.
+/// Logs and deferred tasks are processed with [`finish_event`].
+pub(crate) async fn dispatch_fetch(
+    map: &js_sys::Map,
+    mut ctx: Context,
+    first: Option<&dyn Handler>,
+    config: ServiceConfig,
+) -> Result<JsValue, JsValue> {
+    let mut is_err = false;
+    let mut req = Request::from_js(map)?;
+    req.set_max_body_size(config.max_body_size);
+    // HEAD is handled like GET, and the body is removed from the response
+    req.route_head_as_get();
+    let mut handler_result = match (config.max_body_size, req.content_length()) {
+        (Some(limit), Some(len)) if len > limit => Err(handler_return(413, "Payload Too Large")),
+        _ => Ok(()),
+    };
+    #[cfg(feature = "compression")]
+    if handler_result.is_ok() && config.decompress_requests && req.content_encoding().is_some() {
+        if let Err(e) = req.decompress().await {
+            log!(ctx, Severity::Warning, _:"decompress", error: &e);
+            handler_result = Err(HandlerReturn::from(e));
+        }
+    }
+    if handler_result.is_ok() {
+        for middleware in config.middleware.iter() {
+            handler_result = middleware.before(&req, &mut ctx).await;
+            if let Some(url) = ctx.take_rewrite() {
+                req.set_url(url);
+            }
+            if ctx.is_internal_error().is_some() {
+                (config.internal_error_handler)(&req, &mut ctx);
+                is_err = true;
+                break;
+            }
+            if handler_result.is_err() || !ctx.response().is_unset() {
+                break;
+            }
+        }
+    }
+    for handler in first
+        .into_iter()
+        .chain(config.handlers.iter().map(|h| h.as_ref()))
+    {
+        // request may have been answered before any handler was invoked
+        if handler_result.is_err() || !ctx.response().is_unset() {
+            break;
+        }
         handler_result = handler.handle(&req, &mut ctx).await;
+        // handlers after this one see the rewritten url
+        if let Some(url) = ctx.take_rewrite() {
+            req.set_url(url);
+        }
         if ctx.is_internal_error().is_some() {
             (config.internal_error_handler)(&req, &mut ctx);
             is_err = true;
@@ -218,8 +372,16 @@ pub async fn service_request(req: JsValue, config: ServiceConfig) -> Result<JsVa
         // the not-found handler might return a static page or redirect
         (config.not_found_handler)(&req, &mut ctx);
     }
+    for middleware in config.middleware.iter().rev() {
+        middleware.after(&req, &mut ctx).await;
+    }
+    if req.is_head() {
+        ctx.response().set_head();
+    }
     let response = ctx.take_response();
-    if response.get_status() < 200 || response.get_status() > 307 {
+    // 101 (Switching Protocols) is the successful response to a websocket upgrade
+    if (response.get_status() < 200 && response.get_status() != 101) || response.get_status() > 307
+    {
         is_err = true;
     }
     let severity = if response.get_status() == 404 {
@@ -230,18 +392,29 @@ pub async fn service_request(req: JsValue, config: ServiceConfig) -> Result<JsVa
         Severity::Info
     };
     log!(ctx, severity, _:"service", method: req.method(), url: req.url(), status: response.get_status());
+    finish_event(map, &mut ctx, config.logger, "http", is_err).await?;
+    Ok(response.into_js())
+}
+
+/// Completes processing of an event (fetch, scheduled, etc.) by sending logs and running deferred tasks.
+/// If an error occurred, logs are sent immediately and deferred tasks are not run.
+/// Otherwise (the fast path), logs and tasks are passed in a Promise to event.waitUntil,
+/// so they are processed after the response is returned.
+pub(crate) async fn finish_event(
+    map: &js_sys::Map,
+    ctx: &mut Context,
+    logger: Box<dyn Logger>,
+    sub: &'static str,
+    is_err: bool,
+) -> Result<(), JsValue> {
     if is_err {
         // if any error occurred, send logs now; fast path (on success) defers logging
         // also, if there was an error, don't execute deferred tasks
-        let _ = config
-            .logger
-            .send("http", ctx.take_logs())
-            .await
-            .map_err(|e| {
-                ctx.response()
-                    .header("X-service-log-err-ret", e.to_string())
-                    .unwrap()
-            });
+        // the response has already been taken (and scheduled, queue, and alarm events
+        // have none), so errors sending logs are reported to the console
+        if let Err(e) = logger.send(sub, ctx.take_logs()).await {
+            log_log_error(e);
+        }
     } else {
         // From incoming request, extract 'event' object, and get ref to its 'waitUntil' function
         let js_event =
@@ -253,12 +426,14 @@ pub async fn service_request(req: JsValue, config: ServiceConfig) -> Result<JsVa
         // this should always return OK (event has waitUntil property) unless api is broken.
         let promise = deferred_promise(Box::new(DeferredData {
             tasks: ctx.take_tasks(),
+            local_tasks: ctx.take_local_tasks(),
             logs: ctx.take_logs(),
-            logger: config.logger,
+            logger,
+            sub,
         }));
         let _ = wait_func.call1(&js_event, &promise); // todo: handle result
     }
-    Ok(response.into_js())
+    Ok(())
 }
 
 /// Default implementation of internal error handler
@@ -291,7 +466,7 @@ pub fn default_not_found_handler(req: &Request, ctx: &mut Context) {
 fn deferred_promise(args: Box<DeferredData>) -> js_sys::Promise {
     wasm_bindgen_futures::future_to_promise(async move {
         // send first set of logs
-        if let Err(e) = args.logger.send("http", args.logs).await {
+        if let Err(e) = args.logger.send(args.sub, args.logs).await {
             log_log_error(e);
         }
         // run each deferred task
@@ -301,10 +476,13 @@ fn deferred_promise(args: Box<DeferredData>) -> js_sys::Promise {
         for t in args.tasks.iter() {
             t.run(&run_ctx).await;
         }
+        for fut in args.local_tasks.into_iter() {
+            fut.await;
+        }
 
         // if any logs were generated during processing of deferred tasks, send those
         let logs = run_ctx.log_queue.borrow_mut().take();
-        if let Err(e) = args.logger.send("http", logs).await {
+        if let Err(e) = args.logger.send(args.sub, logs).await {
             log_log_error(e);
         }
         // all done, return nothing
@@ -322,7 +500,7 @@ fn check_defined(v: JsValue, msg: &str) -> Result<JsValue, JsValue> {
 
 /// logging fallback: if we can't send to external logger,
 /// log to "console" so it can be seen in worker logs
-fn log_log_error(e: Box<dyn std::error::Error>) {
+pub(crate) fn log_log_error(e: Box<dyn std::error::Error>) {
     web_sys::console::log_1(&wasm_bindgen::JsValue::from_str(&format!(
         "Error sending logs: {:?}",
         e
@@ -0,0 +1,269 @@
+use crate::js_values::{call_method, get_prop};
+use crate::{finish_event, Context, Env, Error, HttpDate};
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use service_logging::{log, Logger, Severity};
+use wasm_bindgen::JsValue;
+
+/// Acknowledgement state of a queue message
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AckState {
+    /// Message has not been explicitly acknowledged or retried.
+    /// If the handler returns Ok, pending messages are acknowledged implicitly;
+    /// if the handler returns an error, they will be retried.
+    Pending,
+    /// Message was processed and should not be redelivered
+    Ack,
+    /// Message should be redelivered in a later batch
+    Retry,
+}
+
+/// Message received from a Cloudflare Queue, with a deserialized body
+#[derive(Debug)]
+pub struct QueueMessage<T> {
+    id: String,
+    timestamp: HttpDate,
+    attempts: u32,
+    body: T,
+    state: AckState,
+}
+
+impl<T> QueueMessage<T> {
+    /// Creates a queue message. This is used internally for messages received from the runtime,
+    /// and can be used in tests to construct messages for a [`MessageBatch`].
+    pub fn new<S: Into<String>>(id: S, timestamp: HttpDate, body: T) -> Self {
+        QueueMessage {
+            id: id.into(),
+            timestamp,
+            attempts: 1,
+            body,
+            state: AckState::Pending,
+        }
+    }
+
+    /// Returns the unique message id
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns the time the message was sent
+    pub fn timestamp(&self) -> HttpDate {
+        self.timestamp
+    }
+
+    /// Returns the number of times delivery of this message has been attempted (starting at 1)
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Returns the message body
+    pub fn body(&self) -> &T {
+        &self.body
+    }
+
+    /// Marks the message as successfully processed
+    pub fn ack(&mut self) {
+        self.state = AckState::Ack;
+    }
+
+    /// Marks the message for redelivery
+    pub fn retry(&mut self) {
+        self.state = AckState::Retry;
+    }
+
+    /// Returns the acknowledgement state
+    pub fn state(&self) -> AckState {
+        self.state
+    }
+}
+
+/// Batch of messages received from a Cloudflare Queue
+#[derive(Debug)]
+pub struct MessageBatch<T> {
+    queue: String,
+    messages: Vec<QueueMessage<T>>,
+}
+
+impl<T> MessageBatch<T> {
+    /// Creates a batch of messages. This can be used in tests
+    /// to invoke a [`QueueHandler`] natively, without the worker runtime.
+    pub fn new<S: Into<String>>(queue: S, messages: Vec<QueueMessage<T>>) -> Self {
+        MessageBatch {
+            queue: queue.into(),
+            messages,
+        }
+    }
+
+    /// Returns the name of the queue
+    pub fn queue(&self) -> &str {
+        &self.queue
+    }
+
+    /// Returns the messages in the batch
+    pub fn messages(&self) -> &[QueueMessage<T>] {
+        &self.messages
+    }
+
+    /// Returns mutable messages, for ack/retry of individual messages
+    pub fn messages_mut(&mut self) -> &mut [QueueMessage<T>] {
+        &mut self.messages
+    }
+
+    /// Marks all messages in the batch as successfully processed
+    pub fn ack_all(&mut self) {
+        self.messages.iter_mut().for_each(|m| m.ack());
+    }
+
+    /// Marks all messages in the batch for redelivery
+    pub fn retry_all(&mut self) {
+        self.messages.iter_mut().for_each(|m| m.retry());
+    }
+}
+
+/// Trait for handling batches of messages from a Cloudflare Queue.
+/// Message bodies are deserialized (from json-compatible values) into `Message`.
+///
+///```rust
+/// use serde::Deserialize;
+/// use service_logging::{Severity::Info,log,Logger};
+/// use wasm_service::{Context,Error,MessageBatch,QueueHandler};
+/// use async_trait::async_trait;
+///
+/// #[derive(Deserialize)]
+/// struct Job { name: String }
+///
+/// struct JobRunner {}
+/// #[async_trait(?Send)]
+/// impl QueueHandler for JobRunner {
+///     type Message = Job;
+///     async fn handle_batch(&self, batch: &mut MessageBatch<Job>, ctx: &mut Context) -> Result<(), Error> {
+///         for msg in batch.messages_mut() {
+///             log!(ctx, Info, _:"job", name: &msg.body().name);
+///             msg.ack();
+///         }
+///         Ok(())
+///     }
+/// }
+///```
+#[async_trait(?Send)]
+pub trait QueueHandler {
+    /// Type of message body
+    type Message: DeserializeOwned;
+
+    /// Process a batch of messages. Each message may be acknowledged or retried individually.
+    /// If an error is returned, all messages that were not explicitly acknowledged are retried.
+    async fn handle_batch(
+        &self,
+        batch: &mut MessageBatch<Self::Message>,
+        ctx: &mut Context,
+    ) -> Result<(), Error>;
+}
+
+/// Configuration parameters for queue consumers
+pub struct QueueConfig<H: QueueHandler> {
+    /// Logger
+    pub logger: Box<dyn Logger>,
+
+    /// Batch handler
+    pub handler: H,
+}
+
+/// Entrypoint for queue consumers. Deserializes the message batch, invokes the [`QueueHandler`],
+/// applies ack/retry for each message, sends logs to the Logger, and runs deferred tasks.
+/// The parameter is a javascript Map with the following keys:
+/// - "batch": the MessageBatch received by the queue() handler
+/// - "event": the execution context, used for ctx.waitUntil
+/// - "env": (optional) environment bindings
+///
+/// Messages whose body can't be deserialized are logged and retried,
+/// so they will eventually be sent to the dead letter queue, if one is configured.
+/// Returns Err if the handler failed, so that pending messages are retried by the runtime.
+pub async fn service_queue<H: QueueHandler>(
+    input: JsValue,
+    config: QueueConfig<H>,
+) -> Result<(), JsValue> {
+    let map = js_sys::Map::from(input);
+    let js_batch = map.get(&"batch".into());
+    if js_batch.is_undefined() {
+        return Err(JsValue::from_str("invalid_batch"));
+    }
+    let mut ctx = Context::default();
+    ctx.set_env(Env::from(map.get(&"env".into())));
+
+    let queue = get_prop(&js_batch, "queue")
+        .and_then(|v| v.as_string())
+        .unwrap_or_default();
+    let mut js_messages = Vec::new();
+    let mut messages = Vec::new();
+    for js_msg in js_sys::Array::from(&get_prop(&js_batch, "messages").unwrap_or_default()).iter() {
+        match message_from_js::<H::Message>(&js_msg) {
+            Ok(msg) => {
+                messages.push(msg);
+                js_messages.push(js_msg);
+            }
+            Err(e) => {
+                log!(ctx, Severity::Error, _:"queue", queue: &queue, error: e,
+                    id: get_prop(&js_msg, "id").and_then(|v| v.as_string()).unwrap_or_default());
+                let _ = call_method(&js_msg, "retry", &js_sys::Array::new());
+            }
+        }
+    }
+    let mut batch = MessageBatch::new(queue, messages);
+    let result = config.handler.handle_batch(&mut batch, &mut ctx).await;
+
+    // report ack/retry to the runtime
+    for (msg, js_msg) in batch.messages.iter().zip(js_messages.iter()) {
+        let method = match msg.state {
+            AckState::Ack => "ack",
+            AckState::Retry => "retry",
+            AckState::Pending => continue,
+        };
+        if let Err(e) = call_method(js_msg, method, &js_sys::Array::new()) {
+            log!(ctx, Severity::Warning, _:"queue", id: &msg.id, method: method, error: e);
+        }
+    }
+    let error = match (&result, ctx.is_internal_error()) {
+        (Err(e), _) => Some(e.to_string()),
+        (Ok(_), Some(e)) => Some(e.to_string()),
+        _ => None,
+    };
+    let acked = batch
+        .messages
+        .iter()
+        .filter(|m| m.state == AckState::Ack)
+        .count();
+    match error {
+        Some(ref e) => log!(ctx, Severity::Error, _:"queue", queue: &batch.queue,
+            count: batch.messages.len(), acked: acked, error: e),
+        None => log!(ctx, Severity::Info, _:"queue", queue: &batch.queue,
+            count: batch.messages.len(), acked: acked),
+    }
+    finish_event(&map, &mut ctx, config.logger, "queue", error.is_some()).await?;
+    match error {
+        Some(e) => Err(JsValue::from_str(&e)),
+        None => Ok(()),
+    }
+}
+
+/// Converts javascript queue message to QueueMessage. The body is converted to json
+/// and then deserialized, so it must be a json-compatible value.
+fn message_from_js<T: DeserializeOwned>(js_msg: &JsValue) -> Result<QueueMessage<T>, Error> {
+    let body = get_prop(js_msg, "body").unwrap_or(JsValue::NULL);
+    let json = js_sys::JSON::stringify(&body)?
+        .as_string()
+        .unwrap_or_default();
+    let timestamp = get_prop(js_msg, "timestamp")
+        .map(|d| js_sys::Date::from(d).get_time())
+        .unwrap_or_default();
+    let mut msg = QueueMessage::new(
+        get_prop(js_msg, "id")
+            .and_then(|v| v.as_string())
+            .unwrap_or_default(),
+        HttpDate::from((timestamp / 1000.0) as u64),
+        serde_json::from_str(&json)?,
+    );
+    if let Some(attempts) = get_prop(js_msg, "attempts").and_then(|v| v.as_f64()) {
+        msg.attempts = attempts as u32;
+    }
+    Ok(msg)
+}
@@ -0,0 +1,250 @@
+use crate::{etag_for, HttpDate, Method, Request, Response};
+use bytes::{BufMut, Bytes, BytesMut};
+use std::str::FromStr;
+
+/// Maximum number of ranges in one request. Requests with more ranges get the full body,
+/// to limit the cost of generating multipart responses.
+const MAX_RANGES: usize = 16;
+
+/// A range of bytes, with inclusive start and end positions
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ByteRange {
+    /// position of first byte
+    pub start: u64,
+    /// position of last byte (inclusive)
+    pub end: u64,
+}
+
+impl ByteRange {
+    /// Returns the value of the Content-Range header for this range of a body of `total` bytes
+    pub fn content_range(&self, total: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, total)
+    }
+}
+
+/// Result of parsing a Range header
+#[derive(Clone, Debug, PartialEq)]
+pub enum Ranges {
+    /// The header is invalid, or uses a unit other than bytes, and should be ignored:
+    /// the full body is returned with status 200
+    Ignore,
+    /// One or more ranges that overlap the body, clamped to the body length
+    Satisfiable(Vec<ByteRange>),
+    /// None of the ranges overlap the body, and the response should be 416
+    Unsatisfiable,
+}
+
+/// Parses a Range header value (such as `bytes=0-499`, `bytes=500-`, `bytes=-500`,
+/// or a comma-separated list of these) for a body of `len` bytes.
+/// Ranges that start beyond the end of the body are dropped.
+pub fn parse_range(header: &str, len: u64) -> Ranges {
+    let specs = match header.trim().strip_prefix("bytes=") {
+        Some(specs) => specs,
+        None => return Ranges::Ignore,
+    };
+    let mut ranges = Vec::new();
+    for spec in specs.split(',') {
+        let spec = spec.trim();
+        if spec.is_empty() {
+            continue;
+        }
+        let (first, last) = match spec.find('-') {
+            Some(pos) => (spec[..pos].trim(), spec[pos + 1..].trim()),
+            None => return Ranges::Ignore,
+        };
+        let range = match (first.parse::<u64>(), last.parse::<u64>()) {
+            // "start-end"
+            (Ok(start), Ok(end)) if start <= end => Some((start, end.min(len.saturating_sub(1)))),
+            // "start-"
+            (Ok(start), Err(_)) if last.is_empty() => Some((start, len.saturating_sub(1))),
+            // "-suffix_length"
+            (Err(_), Ok(suffix)) if first.is_empty() => match suffix {
+                0 => None,
+                _ => Some((len.saturating_sub(suffix), len.saturating_sub(1))),
+            },
+            _ => return Ranges::Ignore,
+        };
+        if let Some((start, end)) = range {
+            if start < len {
+                ranges.push(ByteRange { start, end });
+            }
+        }
+        if ranges.len() > MAX_RANGES {
+            return Ranges::Ignore;
+        }
+    }
+    if ranges.is_empty() {
+        Ranges::Unsatisfiable
+    } else {
+        Ranges::Satisfiable(ranges)
+    }
+}
+
+/// Returns true if the If-Range header (an etag or http date) matches
+/// the current version of the resource, or if the header is not present.
+/// Etags use strong comparison, and dates must match exactly.
+/// If this returns false, the Range header should be ignored.
+pub fn if_range_matches(
+    req: &Request,
+    etag: Option<&str>,
+    last_modified: Option<HttpDate>,
+) -> bool {
+    match req.get_header("if-range") {
+        None => true,
+        Some(val) => if_range_value_matches(val.trim(), etag, last_modified),
+    }
+}
+
+fn if_range_value_matches(val: &str, etag: Option<&str>, last_modified: Option<HttpDate>) -> bool {
+    if val.starts_with('"') || val.starts_with("W/") {
+        match etag {
+            Some(etag) => !val.starts_with("W/") && !etag.starts_with("W/") && val == etag.trim(),
+            None => false,
+        }
+    } else {
+        match (HttpDate::from_str(val), last_modified) {
+            (Ok(date), Some(modified)) => date == modified,
+            _ => false,
+        }
+    }
+}
+
+/// Applies the request's Range header to a 200 response with a buffered body.
+/// Used by [`Response::range`].
+pub(crate) fn apply(resp: &mut Response, req: &Request) {
+    // unwrap ok because header values are ascii
+    resp.header("accept-ranges", "bytes").unwrap();
+    // HEAD responses get the headers of the full (200) response, since their body
+    // may be empty even though Content-Length is set
+    if req.method() != Method::GET || req.is_head() || resp.get_status() != 200 || resp.is_stream()
+    {
+        return;
+    }
+    let header = match req.get_header("range") {
+        Some(header) => header,
+        None => return,
+    };
+    let etag = resp.get_header("etag");
+    let last_modified = resp
+        .get_header("last-modified")
+        .and_then(|v| HttpDate::from_str(&v).ok());
+    if !if_range_matches(req, etag.as_deref(), last_modified) {
+        return;
+    }
+    let body = Bytes::copy_from_slice(resp.get_body());
+    let total = body.len() as u64;
+    match parse_range(&header, total) {
+        Ranges::Ignore => {}
+        Ranges::Unsatisfiable => {
+            resp.status(416)
+                .header("content-range", format!("bytes */{}", total))
+                .unwrap()
+                .body(Vec::new());
+        }
+        Ranges::Satisfiable(ranges) if ranges.len() == 1 => {
+            let range = ranges[0];
+            resp.status(206)
+                .header("content-range", range.content_range(total))
+                .unwrap()
+                .body(body.slice(range.start as usize..=range.end as usize));
+        }
+        Ranges::Satisfiable(ranges) => {
+            let boundary = etag_for(&body).trim_matches('"').replace('-', "");
+            let content_type = resp.get_header("content-type");
+            let multipart = multipart_body(&body, &ranges, content_type.as_deref(), &boundary);
+            resp.status(206)
+                .content_type(format!("multipart/byteranges; boundary={}", boundary))
+                .unwrap()
+                .body(multipart);
+        }
+    }
+}
+
+/// Generates a multipart/byteranges body
+fn multipart_body(
+    body: &[u8],
+    ranges: &[ByteRange],
+    content_type: Option<&str>,
+    boundary: &str,
+) -> Bytes {
+    let total = body.len() as u64;
+    let mut buf = BytesMut::new();
+    for range in ranges.iter() {
+        buf.put_slice(format!("--{}\r\n", boundary).as_bytes());
+        if let Some(ctype) = content_type {
+            buf.put_slice(format!("Content-Type: {}\r\n", ctype).as_bytes());
+        }
+        buf.put_slice(format!("Content-Range: {}\r\n\r\n", range.content_range(total)).as_bytes());
+        buf.put_slice(&body[range.start as usize..=range.end as usize]);
+        buf.put_slice(b"\r\n");
+    }
+    buf.put_slice(format!("--{}--\r\n", boundary).as_bytes());
+    buf.freeze()
+}
+
+#[test]
+fn parse_ranges() {
+    let r = |start, end| ByteRange { start, end };
+    assert_eq!(
+        parse_range("bytes=0-499", 1000),
+        Ranges::Satisfiable(vec![r(0, 499)])
+    );
+    assert_eq!(
+        parse_range("bytes=500-", 1000),
+        Ranges::Satisfiable(vec![r(500, 999)])
+    );
+    assert_eq!(
+        parse_range("bytes=-100", 1000),
+        Ranges::Satisfiable(vec![r(900, 999)])
+    );
+    assert_eq!(
+        parse_range("bytes=-2000", 1000),
+        Ranges::Satisfiable(vec![r(0, 999)])
+    );
+    assert_eq!(
+        parse_range("bytes=900-2000", 1000),
+        Ranges::Satisfiable(vec![r(900, 999)])
+    );
+    assert_eq!(
+        parse_range("bytes=0-9, 20-29,2000-", 1000),
+        Ranges::Satisfiable(vec![r(0, 9), r(20, 29)])
+    );
+    assert_eq!(parse_range("bytes=1000-", 1000), Ranges::Unsatisfiable);
+    assert_eq!(parse_range("bytes=-0", 1000), Ranges::Unsatisfiable);
+    assert_eq!(parse_range("bytes=0-", 0), Ranges::Unsatisfiable);
+    assert_eq!(parse_range("items=0-9", 1000), Ranges::Ignore);
+    assert_eq!(parse_range("bytes=9-0", 1000), Ranges::Ignore);
+    assert_eq!(parse_range("bytes=abc", 1000), Ranges::Ignore);
+    let many = format!("bytes={}", vec!["0-1"; MAX_RANGES + 1].join(","));
+    assert_eq!(parse_range(&many, 1000), Ranges::Ignore);
+}
+
+#[test]
+fn if_range() {
+    let modified = HttpDate::from(1_600_000_000u64);
+    let date = modified.to_string();
+    assert!(if_range_value_matches("\"v1\"", Some("\"v1\""), None));
+    assert!(!if_range_value_matches("\"v1\"", Some("\"v2\""), None));
+    assert!(!if_range_value_matches("W/\"v1\"", Some("W/\"v1\""), None));
+    assert!(!if_range_value_matches("\"v1\"", None, Some(modified)));
+    assert!(if_range_value_matches(&date, None, Some(modified)));
+    assert!(!if_range_value_matches(
+        &date,
+        None,
+        Some(HttpDate::from(1_600_000_001u64))
+    ));
+}
+
+#[test]
+fn multipart_ranges() {
+    let ranges = [
+        ByteRange { start: 0, end: 1 },
+        ByteRange { start: 4, end: 5 },
+    ];
+    let body = multipart_body(b"abcdefg", &ranges, Some("text/plain"), "XYZ");
+    assert_eq!(
+        &body[..],
+        &b"--XYZ\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/7\r\n\r\nab\r\n\
+--XYZ\r\nContent-Type: text/plain\r\nContent-Range: bytes 4-5/7\r\n\r\nef\r\n--XYZ--\r\n"[..]
+    );
+}
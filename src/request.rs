@@ -1,19 +1,50 @@
 use crate::js_values;
-use crate::{Error, Method};
+use crate::{BodyStream, Error, Method};
+use bytes::Bytes;
+use futures::StreamExt;
+use js_sys::Uint8Array;
 use serde::de::DeserializeOwned;
 use std::borrow::Cow;
+use std::cell::{OnceCell, RefCell};
 use url::Url;
-use wasm_bindgen::JsValue;
+use wasm_bindgen::{JsCast, JsValue};
 
 /// Incoming HTTP request (to Worker).
-#[derive(Debug, Clone)]
+///
+/// The request body may be passed from javascript as a buffer (Uint8Array)
+/// or as a ReadableStream. A stream body is not read until the handler asks for it,
+/// either as a stream of chunks with [`body_stream`](Request::body_stream),
+/// or buffered with [`buffer`](Request::buffer).
+///
+/// A clone has the same method, url, headers, and buffered body, but not an unread
+/// body stream, which can only be read once: if the body is a stream that has not been
+/// buffered, the clone has an empty body.
+#[derive(Debug)]
 pub struct Request {
     method: Method,
     url: Url,
     headers: web_sys::Headers,
-    body: Option<Vec<u8>>,
+    body: OnceCell<Vec<u8>>,
+    decoded: OnceCell<Vec<u8>>,
+    stream: RefCell<Option<JsValue>>,
+    max_body_size: Option<u64>,
+    head: bool,
+}
+
+impl Clone for Request {
+    fn clone(&self) -> Self {
+        Request {
+            method: self.method,
+            url: self.url.clone(),
+            headers: self.headers.clone(),
+            body: self.body.clone(),
+            decoded: self.decoded.clone(),
+            stream: RefCell::new(None),
+            max_body_size: self.max_body_size,
+            head: self.head,
+        }
+    }
 }
-unsafe impl Sync for Request {}
 
 impl Request {
     /// Creates Request object representing incoming HTTP request
@@ -23,17 +54,31 @@ impl Request {
         headers: web_sys::Headers,
         body: Option<Vec<u8>>,
     ) -> Request {
+        let cell = OnceCell::new();
+        if let Some(body) = body {
+            let _ = cell.set(body);
+        }
         Request {
             method,
             url,
             headers,
-            body,
+            body: cell,
+            decoded: OnceCell::new(),
+            stream: RefCell::new(None),
+            max_body_size: None,
+            head: false,
         }
     }
 
+    /// Replaces the url, when the request is rewritten by a handler
+    pub(crate) fn set_url(&mut self, url: Url) {
+        self.url = url;
+    }
+
     /// Creates Request from javascript object
     pub(crate) fn from_js(map: &js_sys::Map) -> Result<Self, JsValue> {
-        Ok(Request::new(
+        let body = map.get(&JsValue::from_str("body"));
+        let mut req = Request::new(
             Method::from(
                 &js_values::get_map_str(&map, "method")
                     .ok_or_else(|| JsValue::from_str("invalid_req.method"))?,
@@ -45,15 +90,57 @@ impl Request {
             .map_err(|e| JsValue::from_str(&format!("invalid req.url:{}", e.to_string())))?,
             js_values::get_map_headers(&map, "headers")
                 .ok_or_else(|| JsValue::from_str("invalid_req"))?,
-            js_values::get_map_bytes(&map, "body"),
-        ))
+            None,
+        );
+        if body.is_instance_of::<Uint8Array>() {
+            let _ = req.body.set(Uint8Array::from(body).to_vec());
+        } else if !body.is_undefined() && !body.is_null() {
+            req.stream = RefCell::new(Some(body));
+        }
+        Ok(req)
     }
 
-    /// Returns the HTTP method
+    /// Sets the maximum body size. If the body is larger,
+    /// reading it fails with [`Error::PayloadTooLarge`]
+    pub fn set_max_body_size(&mut self, max_body_size: Option<u64>) {
+        self.max_body_size = max_body_size;
+    }
+
+    /// Returns the length of the body, if known: the length of the buffered body,
+    /// or the value of the Content-Length header if the body has not been read.
+    pub fn content_length(&self) -> Option<u64> {
+        match self.body.get() {
+            Some(body) => Some(body.len() as u64),
+            None => self
+                .get_header("content-length")
+                .and_then(|v| v.trim().parse().ok()),
+        }
+    }
+
+    /// Returns the HTTP method.
+    /// HEAD requests received by `service_request` are routed as GET,
+    /// so this returns GET for them; use [`is_head`](Request::is_head) to distinguish them.
     pub fn method(&self) -> Method {
         self.method
     }
 
+    /// Returns true if the client sent a HEAD request.
+    /// The response headers are returned to the client, but the body is not.
+    /// Handlers may use this to avoid generating the body, but should
+    /// set the same headers as for GET (Content-Length is set automatically
+    /// from the body, if the handler doesn't set it).
+    pub fn is_head(&self) -> bool {
+        self.head || self.method == Method::HEAD
+    }
+
+    /// Changes a HEAD request to GET, so that it is handled like GET
+    pub(crate) fn route_head_as_get(&mut self) {
+        if self.method == Method::HEAD {
+            self.method = Method::GET;
+            self.head = true;
+        }
+    }
+
     /// Returns the parsed url
     pub fn url(&self) -> &Url {
         &self.url
@@ -78,20 +165,116 @@ impl Request {
         self.headers.has(name).unwrap_or(false)
     }
 
-    /// Returns true if the body is empty
+    /// Returns true if this is a request to upgrade the connection to a websocket
+    pub fn is_websocket_upgrade(&self) -> bool {
+        self.get_header("upgrade")
+            .map(|v| v.trim().eq_ignore_ascii_case("websocket"))
+            .unwrap_or(false)
+    }
+
+    /// Returns true if the body is empty. If the body is an unread stream,
+    /// it is considered empty only if the Content-Length header is 0.
     pub fn is_empty(&self) -> bool {
-        self.body.is_none() || self.body.as_ref().unwrap().is_empty()
+        match self.body.get() {
+            Some(body) => body.is_empty(),
+            None => self.stream.borrow().is_none() || self.content_length() == Some(0),
+        }
     }
 
     /// Returns request body as byte vector, or None if body is empty
+    /// or is a stream that has not been buffered (see [`buffer`](Request::buffer)).
+    /// If the body has been decompressed, returns the decompressed body.
     pub fn body(&self) -> Option<&Vec<u8>> {
-        self.body.as_ref()
+        self.decoded.get().or_else(|| self.body.get())
+    }
+
+    /// Returns the Content-Encoding of the body, or None if the body is not encoded
+    /// (the header is missing or "identity"). After the body has been decompressed,
+    /// this returns None, although the header retains its original value.
+    pub fn content_encoding(&self) -> Option<String> {
+        if self.decoded.get().is_some() {
+            return None;
+        }
+        self.get_header("content-encoding")
+            .map(|v| v.trim().to_ascii_lowercase())
+            .filter(|v| !v.is_empty() && v != "identity")
+    }
+
+    /// Reads the body, if necessary, and decompresses it according to its Content-Encoding
+    /// (gzip, deflate, or br, or a comma-separated list of these).
+    /// After this completes, [`body`](Request::body) and [`json`](Request::json)
+    /// return the decompressed body. If the body is not encoded, it is returned unchanged.
+    ///
+    /// To protect against "zip bombs", decompression fails with [`Error::PayloadTooLarge`]
+    /// if the decompressed size exceeds the maximum body size, or 16MiB if no maximum is set.
+    /// Unsupported encodings fail with [`Error::UnsupportedEncoding`], and corrupt data
+    /// fails with [`Error::Decompress`].
+    ///
+    /// Requires the `compression` feature.
+    #[cfg(feature = "compression")]
+    pub async fn decompress(&self) -> Result<&[u8], Error> {
+        if let Some(encoding) = self.content_encoding() {
+            let limit = self
+                .max_body_size
+                .unwrap_or(crate::compress::DEFAULT_MAX_DECOMPRESSED_SIZE);
+            let decoded = crate::compress::decode(&encoding, self.buffer().await?, limit)?;
+            let _ = self.decoded.set(decoded);
+        }
+        self.buffer().await
+    }
+
+    /// Reads the entire body into memory, if it hasn't been read already, and returns it.
+    /// After this completes, the body is also available from [`body`](Request::body)
+    /// and [`json`](Request::json).
+    /// Fails with [`Error::PayloadTooLarge`] if the body exceeds the maximum body size.
+    pub async fn buffer(&self) -> Result<&[u8], Error> {
+        if self.body.get().is_none() {
+            let mut buf = Vec::new();
+            let mut stream = self.body_stream();
+            while let Some(chunk) = stream.next().await {
+                buf.extend_from_slice(&chunk?);
+            }
+            let _ = self.body.set(buf);
+        }
+        Ok(self.body().map(|v| v.as_slice()).unwrap_or_default())
+    }
+
+    /// Returns the body as a stream of chunks. A stream body can only be read once:
+    /// after it has been read with this method, `body()` and `buffer()` return an empty body.
+    /// If the body was already buffered, the stream returns the buffer in one chunk.
+    /// If the body exceeds the maximum body size, the stream returns [`Error::PayloadTooLarge`].
+    pub fn body_stream(&self) -> BodyStream {
+        if let Some(body) = self.body() {
+            return Box::pin(futures::stream::once(futures::future::ready(Ok(
+                Bytes::from(body.clone()),
+            ))));
+        }
+        let raw = match self.stream.borrow_mut().take() {
+            Some(raw) => raw,
+            None => return Box::pin(futures::stream::empty()),
+        };
+        let limit = self.max_body_size;
+        let mut total: u64 = 0;
+        let stream = wasm_streams::ReadableStream::from_raw(raw.unchecked_into())
+            .into_stream()
+            .map(move |chunk| {
+                let bytes = Bytes::from(Uint8Array::new(&chunk?).to_vec());
+                total += bytes.len() as u64;
+                match limit {
+                    Some(limit) if total > limit => Err(Error::PayloadTooLarge(limit)),
+                    _ => Ok(bytes),
+                }
+            });
+        Box::pin(stream)
     }
 
     /// Interpret body as json object.
+    /// If the body is a stream, it must be buffered first with [`buffer`](Request::buffer).
     pub fn json<T: DeserializeOwned>(&self) -> Result<T, Error> {
-        if let Some(vec) = self.body.as_ref() {
+        if let Some(vec) = self.body() {
             Ok(serde_json::from_slice(vec)?)
+        } else if self.stream.borrow().is_some() {
+            Err(Error::Other("body has not been buffered".to_string()))
         } else {
             Err(Error::Other("body is empty".to_string()))
         }
@@ -1,7 +1,8 @@
-use crate::Error;
+use crate::{CacheControl, Error, EventSender, Request, WebSocket};
 use bytes::Bytes;
+use futures::{Stream, StreamExt};
 use serde::Serialize;
-use std::fmt;
+use std::{fmt, pin::Pin, time::Duration};
 use wasm_bindgen::JsValue;
 
 /// Worker response for HTTP requests.
@@ -12,6 +13,8 @@ pub struct Response {
     headers: Option<web_sys::Headers>,
     body: Body,
     unset: bool,
+    websocket: Option<WebSocket>,
+    head: bool,
 }
 
 impl Default for Response {
@@ -21,6 +24,8 @@ impl Default for Response {
             headers: None,
             body: Body::from(Bytes::new()),
             unset: true,
+            websocket: None,
+            head: false,
         }
     }
 }
@@ -72,6 +77,76 @@ impl Response {
         Ok(self)
     }
 
+    /// Appends a value to a header for this response. If the header is already set,
+    /// the value is added to the existing comma-separated list (as for `Vary`)
+    pub fn append_header<K: AsRef<str>, V: AsRef<str>>(
+        &mut self,
+        key: K,
+        val: V,
+    ) -> Result<&mut Self, Error> {
+        if self.headers.is_none() {
+            self.headers = Some(web_sys::Headers::new().unwrap());
+        }
+        if let Some(ref mut headers) = self.headers {
+            headers.append(key.as_ref(), val.as_ref())?;
+        }
+        Ok(self)
+    }
+
+    /// Removes a header from this response
+    pub fn remove_header<K: AsRef<str>>(&mut self, key: K) -> Result<&mut Self, Error> {
+        if let Some(ref mut headers) = self.headers {
+            headers.delete(key.as_ref())?;
+        }
+        Ok(self)
+    }
+
+    /// Completes a websocket upgrade: sets status to 101 (Switching Protocols),
+    /// and returns the client socket of a [`WebSocketPair`](crate::WebSocketPair) to the client.
+    pub fn websocket(&mut self, client: WebSocket) -> &mut Self {
+        self.websocket = Some(client);
+        self.status(101)
+    }
+
+    /// Sets the body to a stream of Server-Sent Events, and sets headers for `text/event-stream`,
+    /// with caching and proxy buffering disabled. Events are sent to the client with the
+    /// returned [`EventSender`], which is typically moved to a task that produces events
+    /// after the handler returns. If `keep_alive` is set, a comment line is sent
+    /// after each interval with no events, so that idle connections are not closed.
+    pub fn event_stream(&mut self, keep_alive: Option<Duration>) -> EventSender {
+        let (sender, body) = crate::sse::event_stream(keep_alive);
+        // unwrap ok because header values are ascii
+        self.content_type("text/event-stream")
+            .unwrap()
+            .header(reqwest::header::CACHE_CONTROL, "no-cache")
+            .unwrap()
+            .header("x-accel-buffering", "no")
+            .unwrap()
+            .body(body);
+        sender
+    }
+
+    /// Sets the Cache-Control header
+    pub fn cache_control(&mut self, policy: &CacheControl) -> &mut Self {
+        // unwrap ok because value is ascii
+        self.header(reqwest::header::CACHE_CONTROL, policy.to_string())
+            .unwrap()
+    }
+
+    /// Applies the request's `Range` header to this response, for partial downloads.
+    /// This should be called after the status, body, and any `ETag` or `Last-Modified`
+    /// headers have been set. `Accept-Ranges: bytes` is always added.
+    ///
+    /// If the request is a GET with a Range header, the status is 200, the body is not a stream,
+    /// and `If-Range` (if present) matches the response's ETag or Last-Modified date,
+    /// the response becomes 206 Partial Content with the requested range and `Content-Range`,
+    /// or a multipart/byteranges body for multiple ranges.
+    /// If no range overlaps the body, the response is 416 Range Not Satisfiable.
+    pub fn range(&mut self, req: &Request) -> &mut Self {
+        crate::range::apply(self, req);
+        self
+    }
+
     /// Sets response content type
     pub fn content_type<T: AsRef<str>>(&mut self, ctype: T) -> Result<&mut Self, Error> {
         self.header(reqwest::header::CONTENT_TYPE, ctype)?;
@@ -84,8 +159,14 @@ impl Response {
     }
 
     /// Returns body of this response.
+    /// If the body is a stream, its contents are not available, and this returns an empty slice.
     pub fn get_body(&self) -> &[u8] {
-        &self.body.inner.as_ref()
+        self.body.as_bytes().map(|b| b.as_ref()).unwrap_or_default()
+    }
+
+    /// Returns true if the body is a stream
+    pub fn is_stream(&self) -> bool {
+        self.body.is_stream()
     }
 
     /// Returns headers for this response, or None if no headers have been set
@@ -93,11 +174,24 @@ impl Response {
         self.headers.as_ref()
     }
 
+    /// Returns the value of a response header, or None if it has not been set
+    pub fn get_header(&self, name: &str) -> Option<String> {
+        self.headers
+            .as_ref()
+            .and_then(|headers| headers.get(name).ok().flatten())
+    }
+
     /// Returns true if the body is empty
     pub fn is_empty(&self) -> bool {
         self.body.is_empty()
     }
 
+    /// Marks this as the response to a HEAD request: the body is removed when the response
+    /// is returned to javascript, and Content-Length is set from the body if it was not set
+    pub(crate) fn set_head(&mut self) {
+        self.head = true;
+    }
+
     /// Converts Response to JsValue
     /// This is destructive to self (removes headers) and is used after
     /// application request handling has completed.
@@ -107,12 +201,27 @@ impl Response {
             &JsValue::from_str("status"),
             &JsValue::from_f64(self.status as f64),
         );
-        map.set(
-            &JsValue::from_str("body"),
-            &js_sys::Uint8Array::from(self.body.inner.as_ref()),
-        );
-        if self.headers.is_some() {
-            let headers = std::mem::take(&mut self.headers).unwrap();
+        if self.get_header("content-encoding").is_some() {
+            // body is already encoded; tell the runtime not to compress it again
+            map.set(
+                &JsValue::from_str("encodeBody"),
+                &JsValue::from_str("manual"),
+            );
+        }
+        if self.head {
+            if !self.body.is_stream() && self.get_header("content-length").is_none() {
+                let len = self.get_body().len().to_string();
+                // unwrap ok because number is ascii
+                self.header(reqwest::header::CONTENT_LENGTH, len).unwrap();
+            }
+            map.set(&JsValue::from_str("body"), &JsValue::NULL);
+        } else {
+            map.set(&JsValue::from_str("body"), &self.body.into_js());
+        }
+        if let Some(ref ws) = self.websocket {
+            map.set(&JsValue::from_str("webSocket"), ws.as_js());
+        }
+        if let Some(headers) = self.headers {
             map.set(&JsValue::from_str("headers"), &JsValue::from(headers));
         } else {
             map.set(
@@ -135,45 +244,99 @@ impl Response {
 }
 
 /// The body of a `Response`.
+/// The body is either a buffer of bytes, or a stream of byte chunks.
 // this is adapted from reqwest::wasm::Body, which is used in requests
 pub struct Body {
-    inner: Bytes,
+    inner: Inner,
+}
+
+/// Stream of body chunks
+pub type BodyStream = Pin<Box<dyn Stream<Item = Result<Bytes, Error>>>>;
+
+enum Inner {
+    Bytes(Bytes),
+    Stream(BodyStream),
 }
 
 impl Body {
-    /// True if the body is empty
+    /// Creates a body from a stream. Chunks are sent to the client as they are generated,
+    /// so the full body does not need to be held in memory. If the stream returns an error,
+    /// the response is aborted.
+    pub fn from_stream<S>(stream: S) -> Body
+    where
+        S: Stream<Item = Result<Bytes, Error>> + 'static,
+    {
+        Body {
+            inner: Inner::Stream(Box::pin(stream)),
+        }
+    }
+
+    /// True if the body is empty. A stream body is never considered empty.
     pub fn is_empty(&self) -> bool {
-        self.inner.is_empty()
+        match &self.inner {
+            Inner::Bytes(bytes) => bytes.is_empty(),
+            Inner::Stream(_) => false,
+        }
+    }
+
+    /// True if the body is a stream
+    pub fn is_stream(&self) -> bool {
+        matches!(self.inner, Inner::Stream(_))
+    }
+
+    /// Returns the body bytes, or None if the body is a stream
+    pub fn as_bytes(&self) -> Option<&Bytes> {
+        match &self.inner {
+            Inner::Bytes(bytes) => Some(bytes),
+            Inner::Stream(_) => None,
+        }
+    }
+
+    /// Converts body to a javascript Uint8Array or ReadableStream
+    fn into_js(self) -> JsValue {
+        match self.inner {
+            Inner::Bytes(bytes) => js_sys::Uint8Array::from(bytes.as_ref()).into(),
+            Inner::Stream(stream) => {
+                let stream = stream.map(|chunk| {
+                    chunk
+                        .map(|bytes| js_sys::Uint8Array::from(bytes.as_ref()).into())
+                        .map_err(|e| JsValue::from_str(&e.to_string()))
+                });
+                wasm_streams::ReadableStream::from_stream(stream)
+                    .into_raw()
+                    .into()
+            }
+        }
     }
 }
 
 impl From<Bytes> for Body {
     #[inline]
     fn from(bytes: Bytes) -> Body {
-        Body { inner: bytes }
+        Body {
+            inner: Inner::Bytes(bytes),
+        }
     }
 }
 
 impl From<Vec<u8>> for Body {
     #[inline]
     fn from(vec: Vec<u8>) -> Body {
-        Body { inner: vec.into() }
+        Bytes::from(vec).into()
     }
 }
 
 impl From<&'static [u8]> for Body {
     #[inline]
     fn from(s: &'static [u8]) -> Body {
-        Body {
-            inner: Bytes::from_static(s),
-        }
+        Bytes::from_static(s).into()
     }
 }
 
 impl From<String> for Body {
     #[inline]
     fn from(s: String) -> Body {
-        Body { inner: s.into() }
+        Bytes::from(s).into()
     }
 }
 
@@ -186,6 +349,8 @@ impl From<&'static str> for Body {
 
 impl fmt::Debug for Body {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_struct("Body").finish()
+        f.debug_struct("Body")
+            .field("stream", &self.is_stream())
+            .finish()
     }
 }
@@ -0,0 +1,458 @@
+use crate::{AssetStore, Context, Error, HandlerReturn, Middleware, Request};
+use async_trait::async_trait;
+use service_logging::{log, Severity};
+use std::collections::{HashMap, HashSet};
+
+/// Middleware that applies redirect, rewrite, and header rules, in the formats of Netlify's
+/// `_redirects` and `_headers` files. Add it to [`ServiceConfig::middleware`](crate::ServiceConfig).
+/// Redirects and rewrites are applied before the handlers, such as
+/// [`StaticAssetHandler`](crate::StaticAssetHandler), run, and headers after,
+/// so header rules replace headers set by the handlers.
+///
+/// Each line of a `_redirects` file has a source path, a destination, and an optional status:
+///```text
+/// # comments (from '#' at the start of a line or after whitespace) and blank lines are ignored
+/// /home              /                 301
+/// /news/*            /blog/:splat
+/// /users/:id         /profile/:id      302
+/// /docs/*            https://docs.example.com/:splat
+/// /app/*             /index.html       200
+///```
+/// In source paths, `:name` matches one path segment, and a trailing `*` matches the rest
+/// of the path; their values replace `:name` and `:splat` in the destination.
+/// The status defaults to 301. A status of 200 is a rewrite: the request is served from
+/// the destination path by the handlers, without redirecting the client.
+/// The first matching rule is used. Rules are always applied, even if an asset exists
+/// at the source path (as if every rule had Netlify's `!` suffix), and rules with
+/// query parameter or country conditions are ignored.
+///
+/// A `_headers` file has unindented path patterns, each followed by indented header lines.
+/// Headers from all matching patterns are set on the response, replacing any values
+/// set by the handlers; a header in more than one matching pattern gets all of their values.
+/// Patterns are matched against the request path after rewrites, so a rewritten
+/// request gets the headers of the path it was served from:
+///```text
+/// /*
+///   X-Frame-Options: DENY
+/// /assets/*
+///   Cache-Control: public, max-age=31536000, immutable
+///```
+///
+///```rust,ignore
+/// let config = ServiceConfig {
+///     middleware: vec![Box::new(Rules::load(&store).await?)],
+///     handlers: vec![Box::new(StaticAssetHandler::new(store))],
+///     ..Default::default()
+/// };
+///```
+pub struct Rules {
+    redirects: Vec<RedirectRule>,
+    headers: Vec<HeaderRule>,
+}
+
+#[derive(Debug, PartialEq)]
+struct RedirectRule {
+    from: String,
+    to: String,
+    status: u16,
+}
+
+#[derive(Debug, PartialEq)]
+struct HeaderRule {
+    pattern: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Rules {
+    /// Creates middleware with no rules
+    pub fn new() -> Self {
+        Rules {
+            redirects: Vec::new(),
+            headers: Vec::new(),
+        }
+    }
+
+    /// Adds redirect and rewrite rules from the contents of a `_redirects` file.
+    /// Invalid lines are ignored.
+    pub fn with_redirects(mut self, text: &str) -> Self {
+        self.redirects.extend(parse_redirects(text));
+        self
+    }
+
+    /// Adds header rules from the contents of a `_headers` file. Invalid lines are ignored.
+    pub fn with_headers(mut self, text: &str) -> Self {
+        self.headers.extend(parse_headers(text));
+        self
+    }
+
+    /// Creates middleware with rules read from the `_redirects` and `_headers` assets in the store,
+    /// if they exist
+    pub async fn load(store: &dyn AssetStore) -> Result<Self, Error> {
+        let mut rules = Self::new();
+        if let Some(md) = store.lookup("_redirects")? {
+            rules = rules.with_redirects(&String::from_utf8_lossy(&store.get(&md).await?));
+        }
+        if let Some(md) = store.lookup("_headers")? {
+            rules = rules.with_headers(&String::from_utf8_lossy(&store.get(&md).await?));
+        }
+        Ok(rules)
+    }
+}
+
+/// Parses `_redirects` file
+fn parse_redirects(text: &str) -> Vec<RedirectRule> {
+    text.lines()
+        .filter_map(|line| {
+            let line = strip_comment(line);
+            let parts = line.split_whitespace().collect::<Vec<&str>>();
+            let status = match parts.len() {
+                2 => 301,
+                3 => parts[2].trim_end_matches('!').parse::<u16>().ok()?,
+                // blank lines, or lines with conditions
+                _ => return None,
+            };
+            if !parts[0].starts_with('/') || !matches!(status, 200 | 301 | 302 | 303 | 307 | 308) {
+                return None;
+            }
+            Some(RedirectRule {
+                from: parts[0].to_string(),
+                to: parts[1].to_string(),
+                status,
+            })
+        })
+        .collect()
+}
+
+/// Removes the comment from the line. A comment begins with '#' at the start of the line
+/// or after whitespace, so '#' can be used in paths and urls (`/guide#intro`).
+fn strip_comment(line: &str) -> &str {
+    let mut after_space = true;
+    for (pos, c) in line.char_indices() {
+        if c == '#' && after_space {
+            return &line[..pos];
+        }
+        after_space = c.is_whitespace();
+    }
+    line
+}
+
+/// Parses `_headers` file
+fn parse_headers(text: &str) -> Vec<HeaderRule> {
+    let mut rules: Vec<HeaderRule> = Vec::new();
+    for line in text.lines() {
+        let content = line.trim();
+        if content.is_empty() || content.starts_with('#') {
+            continue;
+        }
+        if !line.starts_with(char::is_whitespace) {
+            rules.push(HeaderRule {
+                pattern: content.to_string(),
+                headers: Vec::new(),
+            });
+        } else if let (Some(rule), Some((name, value))) = (rules.last_mut(), split_header(content))
+        {
+            rule.headers.push((name, value));
+        }
+    }
+    rules
+}
+
+fn split_header(line: &str) -> Option<(String, String)> {
+    let mut parts = line.splitn(2, ':');
+    let name = parts.next()?.trim();
+    let value = parts.next()?.trim();
+    if name.is_empty() {
+        return None;
+    }
+    Some((name.to_string(), value.to_string()))
+}
+
+/// Matches the url path against the rule pattern. If it matches,
+/// returns the values of placeholders (`:name`), and of the trailing `*` as "splat".
+/// Trailing slashes are ignored.
+fn match_path(pattern: &str, path: &str) -> Option<HashMap<String, String>> {
+    let pattern = pattern
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<&str>>();
+    let path = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<&str>>();
+    let mut values = HashMap::new();
+    for (i, seg) in pattern.iter().enumerate() {
+        if i + 1 == pattern.len() {
+            if let Some(prefix) = seg.strip_suffix('*') {
+                let rest = path[i.min(path.len())..].join("/");
+                let splat = rest.strip_prefix(prefix)?;
+                values.insert("splat".to_string(), splat.to_string());
+                return Some(values);
+            }
+        }
+        let part = path.get(i)?;
+        match seg.strip_prefix(':') {
+            Some(name) => {
+                values.insert(name.to_string(), part.to_string());
+            }
+            None if seg == part => {}
+            None => return None,
+        }
+    }
+    if path.len() == pattern.len() {
+        Some(values)
+    } else {
+        None
+    }
+}
+
+/// Replaces `:name` in the destination with placeholder values
+fn substitute(to: &str, values: &HashMap<String, String>) -> String {
+    let mut out = String::with_capacity(to.len());
+    let mut rest = to;
+    while let Some(pos) = rest.find(':') {
+        out.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+        let len = after
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(after.len());
+        match values.get(&after[..len]) {
+            Some(val) if len > 0 => out.push_str(val),
+            _ => {
+                out.push(':');
+                out.push_str(&after[..len]);
+            }
+        }
+        rest = &after[len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[async_trait(?Send)]
+impl Middleware for Rules {
+    /// Applies the first matching redirect rule. Redirects set the response;
+    /// rewrites change the request url for the handlers.
+    async fn before(&self, req: &Request, ctx: &mut Context) -> Result<(), HandlerReturn> {
+        let path = req.url().path();
+        for rule in self.redirects.iter() {
+            let values = match match_path(&rule.from, path) {
+                Some(values) => values,
+                None => continue,
+            };
+            let mut url = match req.url().join(&substitute(&rule.to, &values)) {
+                Ok(url) => url,
+                Err(_) => continue,
+            };
+            // keep the query string, unless the destination has one
+            if url.query().is_none() {
+                url.set_query(req.url().query());
+            }
+            if rule.status == 200 {
+                // rewrites to other sites would require a proxy
+                if url.origin() != req.url().origin() {
+                    continue;
+                }
+                ctx.rewrite(url);
+            } else {
+                ctx.response()
+                    .header(reqwest::header::LOCATION, url.as_str())?
+                    .status(rule.status);
+            }
+            return Ok(());
+        }
+        Ok(())
+    }
+
+    /// Sets headers from matching header rules
+    async fn after(&self, req: &Request, ctx: &mut Context) {
+        let path = req.url().path();
+        let mut replaced = HashSet::new();
+        for rule in self.headers.iter() {
+            if match_path(&rule.pattern, path).is_none() {
+                continue;
+            }
+            for (name, value) in rule.headers.iter() {
+                // the first value replaces the handler's value, and later values are appended
+                let resp = ctx.response();
+                let result = if replaced.insert(name.to_ascii_lowercase()) {
+                    resp.header(name, value).map(|_| ())
+                } else {
+                    resp.append_header(name, value).map(|_| ())
+                };
+                if let Err(e) = result {
+                    log!(ctx, Severity::Warning, _:"rules", header: name, error: e);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn parse_rules() {
+    let redirects = parse_redirects(
+        "# comment\n\n/a  /b\n/c /d 302  # trailing comment\n/e /f 200!\n\
+         /g id=:id /h 301\n/x /y 404\nbad /z\n/docs /guide#intro 302\n#/i /j\n",
+    );
+    assert_eq!(
+        redirects,
+        vec![
+            RedirectRule {
+                from: "/a".to_string(),
+                to: "/b".to_string(),
+                status: 301
+            },
+            RedirectRule {
+                from: "/c".to_string(),
+                to: "/d".to_string(),
+                status: 302
+            },
+            RedirectRule {
+                from: "/e".to_string(),
+                to: "/f".to_string(),
+                status: 200
+            },
+            RedirectRule {
+                from: "/docs".to_string(),
+                to: "/guide#intro".to_string(),
+                status: 302
+            },
+        ]
+    );
+
+    let headers = parse_headers(
+        "/*\n  X-Frame-Options: DENY\n  Link: <https://a.com>; rel=preload\n\n\
+         # comment\n/assets/*\n\tCache-Control: max-age=60\n  invalid\n",
+    );
+    assert_eq!(
+        headers,
+        vec![
+            HeaderRule {
+                pattern: "/*".to_string(),
+                headers: vec![
+                    ("X-Frame-Options".to_string(), "DENY".to_string()),
+                    (
+                        "Link".to_string(),
+                        "<https://a.com>; rel=preload".to_string()
+                    ),
+                ],
+            },
+            HeaderRule {
+                pattern: "/assets/*".to_string(),
+                headers: vec![("Cache-Control".to_string(), "max-age=60".to_string())],
+            },
+        ]
+    );
+}
+
+#[test]
+fn match_paths() {
+    let get = |pattern: &str, path: &str, name: &str| {
+        match_path(pattern, path).and_then(|values| values.get(name).cloned())
+    };
+    assert!(match_path("/", "/").is_some());
+    assert!(match_path("/about", "/about/").is_some());
+    assert!(match_path("/about", "/about/team").is_none());
+    assert!(match_path("/*", "/").is_some());
+    assert_eq!(
+        get("/news/*", "/news/2020/a", "splat"),
+        Some("2020/a".to_string())
+    );
+    assert_eq!(get("/news/*", "/news", "splat"), Some("".to_string()));
+    assert!(match_path("/news/*", "/newsletter").is_none());
+    assert_eq!(
+        get("/news*", "/newsletter/x", "splat"),
+        Some("letter/x".to_string())
+    );
+    assert_eq!(
+        get("/users/:id/posts", "/users/42/posts", "id"),
+        Some("42".to_string())
+    );
+    assert!(match_path("/users/:id", "/users").is_none());
+
+    let values = match_path("/u/:id/*", "/u/7/a/b").unwrap();
+    assert_eq!(substitute("/profile/:id/:splat", &values), "/profile/7/a/b");
+    assert_eq!(
+        substitute("https://example.com:8080/:id?x=:missing", &values),
+        "https://example.com:8080/7?x=:missing"
+    );
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod test {
+    use super::*;
+    use crate::{Handler, MemoryAssets, Method, Response, StaticAssetHandler, Url};
+    use wasm_bindgen_test::*;
+
+    /// Runs the rules middleware around the static asset handler
+    async fn run(rules: &Rules, assets: &StaticAssetHandler<'_>, url: &str) -> Response {
+        let mut req = Request::new(
+            Method::GET,
+            Url::parse(url).unwrap(),
+            web_sys::Headers::new().unwrap(),
+            None,
+        );
+        let mut ctx = Context::default();
+        rules.before(&req, &mut ctx).await.unwrap();
+        if let Some(url) = ctx.take_rewrite() {
+            req.set_url(url);
+        }
+        if ctx.response().is_unset() {
+            assets.handle(&req, &mut ctx).await.unwrap();
+        }
+        rules.after(&req, &mut ctx).await;
+        ctx.take_response()
+    }
+
+    #[wasm_bindgen_test]
+    async fn rules_with_assets() {
+        let mut store = MemoryAssets::default();
+        store.insert("index.html", "<html></html>", 0);
+        store.insert("assets/app.js", "main()", 0);
+        let assets = StaticAssetHandler::new(store)
+            .with_path_cache("/assets/*", crate::CacheControl::new().no_cache());
+        let rules = Rules::new()
+            .with_redirects("/old /assets/app.js\n/app/* /index.html 200\n")
+            .with_headers(
+                "/*\n  X-Frame-Options: DENY\n  Link: </a.css>\n\
+                 /assets/*\n  Cache-Control: public, max-age=60\n  \
+                 Content-Type: text/x-test\n  Link: </b.css>\n",
+            );
+
+        // header rules replace the handler's headers
+        let resp = run(&rules, &assets, "https://example.com/assets/app.js").await;
+        assert_eq!(resp.get_status(), 200);
+        assert_eq!(
+            resp.get_header("cache-control").as_deref(),
+            Some("public, max-age=60")
+        );
+        assert_eq!(
+            resp.get_header("content-type").as_deref(),
+            Some("text/x-test")
+        );
+        assert_eq!(resp.get_header("x-frame-options").as_deref(), Some("DENY"));
+        assert_eq!(
+            resp.get_header("link").as_deref(),
+            Some("</a.css>, </b.css>")
+        );
+
+        // rewrites are served by the handler, with headers for the rewritten path
+        let resp = run(&rules, &assets, "https://example.com/app/settings").await;
+        assert_eq!(resp.get_status(), 200);
+        assert_eq!(resp.get_body(), b"<html></html>");
+        assert_eq!(resp.get_header("x-frame-options").as_deref(), Some("DENY"));
+        assert_eq!(resp.get_header("cache-control"), None);
+
+        let resp = run(&rules, &assets, "https://example.com/old?x=1").await;
+        assert_eq!(resp.get_status(), 301);
+        assert_eq!(
+            resp.get_header("location").as_deref(),
+            Some("https://example.com/assets/app.js?x=1")
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use crate::js_values;
+use crate::{finish_event, Context, Env, Error, HttpDate};
+use async_trait::async_trait;
+use service_logging::{log, Logger, Severity};
+use wasm_bindgen::JsValue;
+
+/// Trait for handling scheduled events (cron triggers)
+///
+///```rust
+/// use service_logging::{Severity::Info,log,Logger};
+/// use wasm_service::{Context,Error,HttpDate,ScheduledHandler};
+/// use async_trait::async_trait;
+/// struct Cleanup {}
+/// #[async_trait(?Send)]
+/// impl ScheduledHandler for Cleanup {
+///     async fn scheduled(&self, cron: &str, time: HttpDate, ctx: &mut Context) -> Result<(), Error> {
+///         log!(ctx, Info, _:"cleanup", cron: cron, time: time);
+///         Ok(())
+///     }
+/// }
+///```
+#[async_trait(?Send)]
+pub trait ScheduledHandler {
+    /// Process a scheduled event. `cron` is the cron expression of the trigger that fired,
+    /// and `scheduled_time` is the time the event was scheduled to run.
+    /// If an error is returned, logs are sent immediately and deferred tasks are not run.
+    async fn scheduled(
+        &self,
+        cron: &str,
+        scheduled_time: HttpDate,
+        ctx: &mut Context,
+    ) -> Result<(), Error>;
+}
+
+/// Configuration parameters for scheduled events
+pub struct ScheduledConfig {
+    /// Logger
+    pub logger: Box<dyn Logger>,
+
+    /// Scheduled event handlers. All handlers are invoked, in order,
+    /// unless one of them returns an error.
+    pub handlers: Vec<Box<dyn ScheduledHandler>>,
+}
+
+impl Default for ScheduledConfig {
+    /// Default construction of ScheduledConfig does no logging and has no handlers.
+    fn default() -> ScheduledConfig {
+        ScheduledConfig {
+            logger: service_logging::silent_logger(),
+            handlers: Vec::new(),
+        }
+    }
+}
+
+/// Entrypoint for scheduled events (cron triggers). Invokes each [`ScheduledHandler`],
+/// sends logs to the Logger, and runs deferred tasks.
+/// The parameter is a javascript Map with the following keys:
+/// - "cron": cron expression of the trigger (event.cron)
+/// - "scheduledTime": scheduled time in milliseconds since EPOCH (event.scheduledTime)
+/// - "event": the scheduled event, used for event.waitUntil
+/// - "env": (optional) environment bindings
+///
+/// Returns Err if any handler failed, so the failure is reported by the Workers runtime.
+pub async fn service_scheduled(event: JsValue, config: ScheduledConfig) -> Result<(), JsValue> {
+    let map = js_sys::Map::from(event);
+    let cron = js_values::get_map_str(&map, "cron").unwrap_or_default();
+    let scheduled_time = HttpDate::from(
+        (js_values::get_map_f64(&map, "scheduledTime")
+            .ok_or_else(|| JsValue::from_str("invalid_event.scheduledTime"))?
+            / 1000.0) as u64,
+    );
+    let mut ctx = Context::default();
+    ctx.set_env(Env::from(map.get(&"env".into())));
+    let mut result = Ok(());
+    for handler in config.handlers.iter() {
+        result = handler.scheduled(&cron, scheduled_time, &mut ctx).await;
+        if result.is_err() || ctx.is_internal_error().is_some() {
+            break;
+        }
+    }
+    let error = match (&result, ctx.is_internal_error()) {
+        (Err(e), _) => Some(e.to_string()),
+        (Ok(_), Some(e)) => Some(e.to_string()),
+        _ => None,
+    };
+    match error {
+        Some(ref e) => {
+            log!(ctx, Severity::Error, _:"scheduled", cron: &cron, time: scheduled_time, error: e)
+        }
+        None => log!(ctx, Severity::Info, _:"scheduled", cron: &cron, time: scheduled_time),
+    }
+    finish_event(&map, &mut ctx, config.logger, "scheduled", error.is_some()).await?;
+    match error {
+        Some(e) => Err(JsValue::from_str(&e)),
+        None => Ok(()),
+    }
+}
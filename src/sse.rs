@@ -0,0 +1,139 @@
+use crate::js_values::Sleep;
+use crate::{Body, Error};
+use bytes::Bytes;
+use futures::{channel::mpsc, future::Either, Stream, StreamExt};
+use std::{fmt, time::Duration};
+
+/// Comment line sent periodically to keep the connection open
+const KEEP_ALIVE: &str = ":keep-alive\n\n";
+
+/// Server-Sent Event, formatted for a `text/event-stream` response
+///
+/// ```
+/// # use wasm_service::Event;
+/// let ev = Event::new("50%").event("progress").id("7");
+/// assert_eq!(ev.to_string(), "id: 7\nevent: progress\ndata: 50%\n\n");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Event {
+    id: Option<String>,
+    event: Option<String>,
+    data: String,
+    retry: Option<u64>,
+}
+
+impl Event {
+    /// Creates event with the data. Multi-line data is sent as multiple `data:` fields,
+    /// which the browser joins with newlines.
+    pub fn new<T: Into<String>>(data: T) -> Self {
+        Event {
+            data: data.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Creates event with data serialized as json
+    pub fn json<T: serde::Serialize>(value: &T) -> Result<Self, Error> {
+        Ok(Event::new(serde_json::to_string(value)?))
+    }
+
+    /// Sets the event id. The browser sends the last id received in the
+    /// `Last-Event-ID` header when it reconnects.
+    pub fn id<T: Into<String>>(mut self, id: T) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Sets the event type. Events without a type are delivered to the `onmessage` handler.
+    pub fn event<T: Into<String>>(mut self, event: T) -> Self {
+        self.event = Some(event.into());
+        self
+    }
+
+    /// Sets the reconnection time, in milliseconds
+    pub fn retry(mut self, millis: u64) -> Self {
+        self.retry = Some(millis);
+        self
+    }
+}
+
+/// Formats event in `text/event-stream` format
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // newlines would end the field, so they are removed from single-line fields
+        if let Some(ref id) = self.id {
+            writeln!(f, "id: {}", single_line(id))?;
+        }
+        if let Some(ref event) = self.event {
+            writeln!(f, "event: {}", single_line(event))?;
+        }
+        if let Some(retry) = self.retry {
+            writeln!(f, "retry: {}", retry)?;
+        }
+        // "\r\n", "\r", and "\n" all end a line in the event stream format.
+        // lines() is not used, because it drops a trailing empty line.
+        for line in self.data.replace("\r\n", "\n").split(&['\r', '\n'][..]) {
+            if line.is_empty() {
+                writeln!(f, "data:")?;
+            } else {
+                writeln!(f, "data: {}", line)?;
+            }
+        }
+        writeln!(f)
+    }
+}
+
+fn single_line(s: &str) -> String {
+    s.replace(&['\r', '\n'][..], " ")
+}
+
+/// Sends events to the client of an event stream response.
+/// Created with [`Response::event_stream`](crate::Response::event_stream).
+/// The stream ends when the sender is dropped.
+#[derive(Clone, Debug)]
+pub struct EventSender {
+    tx: mpsc::UnboundedSender<Event>,
+}
+
+impl EventSender {
+    /// Sends the event. Returns an error if the client has disconnected.
+    pub fn send(&self, event: Event) -> Result<(), Error> {
+        self.tx
+            .unbounded_send(event)
+            .map_err(|_| Error::Other("event stream closed".to_string()))
+    }
+
+    /// Returns true if the client has disconnected
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+}
+
+/// Creates sender and streaming body for Server-Sent Events.
+/// If `keep_alive` is set, a comment is sent when no event has been sent during the interval.
+pub(crate) fn event_stream(keep_alive: Option<Duration>) -> (EventSender, Body) {
+    let (tx, rx) = mpsc::unbounded();
+    (
+        EventSender { tx },
+        Body::from_stream(event_body(rx, keep_alive)),
+    )
+}
+
+fn event_body(
+    rx: mpsc::UnboundedReceiver<Event>,
+    keep_alive: Option<Duration>,
+) -> impl Stream<Item = Result<Bytes, Error>> {
+    futures::stream::unfold(rx, move |mut rx| async move {
+        let next = match keep_alive {
+            None => rx.next().await.map(|ev| ev.to_string()),
+            Some(interval) => {
+                // the timer is cancelled when an event arrives first
+                match futures::future::select(rx.next(), Sleep::new(interval)).await {
+                    Either::Left((ev, _)) => ev.map(|ev| ev.to_string()),
+                    Either::Right(_) => Some(KEEP_ALIVE.to_string()),
+                }
+            }
+        };
+        next.map(|s| (Ok(Bytes::from(s)), rx))
+    })
+}
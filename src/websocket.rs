@@ -0,0 +1,226 @@
+use crate::js_values::{call_method, get_prop};
+use crate::{Context, Env, Error};
+use async_trait::async_trait;
+use futures::{channel::mpsc, StreamExt};
+use js_sys::{Array, Uint8Array};
+use service_logging::{log, Logger, Severity};
+use wasm_bindgen::{closure::Closure, JsValue};
+
+/// Message received from, or sent to, a WebSocket
+#[derive(Clone, Debug, PartialEq)]
+pub enum WsMessage {
+    /// utf-8 text message
+    Text(String),
+    /// binary message
+    Binary(Vec<u8>),
+}
+
+/// Pair of connected WebSockets. The `client` socket is returned to the client
+/// in the 101 response, with [`Response::websocket`](crate::Response::websocket),
+/// and the `server` socket is handled by the worker.
+///
+///```rust,ignore
+/// if req.is_websocket_upgrade() {
+///     let pair = WebSocketPair::new()?;
+///     pair.server.serve(Box::new(MyWsHandler{}), logger, ctx.env())?;
+///     ctx.response().websocket(pair.client);
+/// }
+///```
+#[derive(Debug)]
+pub struct WebSocketPair {
+    /// socket returned to client
+    pub client: WebSocket,
+    /// socket used by worker
+    pub server: WebSocket,
+}
+
+impl WebSocketPair {
+    /// Creates a new pair of connected sockets
+    pub fn new() -> Result<Self, Error> {
+        let ctor = get_prop(&js_sys::global(), "WebSocketPair")
+            .ok_or_else(|| Error::Js("WebSocketPair is not supported".to_string()))?;
+        let pair = js_sys::Reflect::construct(&js_sys::Function::from(ctor), &Array::new())?;
+        Ok(WebSocketPair {
+            client: WebSocket::from(get_prop(&pair, "0").unwrap_or_default()),
+            server: WebSocket::from(get_prop(&pair, "1").unwrap_or_default()),
+        })
+    }
+}
+
+/// A WebSocket connection
+#[derive(Clone, Debug)]
+pub struct WebSocket {
+    inner: JsValue,
+}
+
+impl WebSocket {
+    /// Sends a text message
+    pub fn send_text(&self, text: &str) -> Result<(), Error> {
+        call_method(&self.inner, "send", &Array::of1(&JsValue::from_str(text)))?;
+        Ok(())
+    }
+
+    /// Sends a binary message
+    pub fn send_bytes(&self, data: &[u8]) -> Result<(), Error> {
+        call_method(&self.inner, "send", &Array::of1(&Uint8Array::from(data)))?;
+        Ok(())
+    }
+
+    /// Sends a message
+    pub fn send(&self, msg: &WsMessage) -> Result<(), Error> {
+        match msg {
+            WsMessage::Text(text) => self.send_text(text),
+            WsMessage::Binary(data) => self.send_bytes(data),
+        }
+    }
+
+    /// Closes the connection, with a status code and reason
+    pub fn close(&self, code: u16, reason: &str) -> Result<(), Error> {
+        call_method(
+            &self.inner,
+            "close",
+            &Array::of2(&JsValue::from_f64(code as f64), &JsValue::from_str(reason)),
+        )?;
+        Ok(())
+    }
+
+    /// Accepts the connection, and processes incoming messages with the handler.
+    /// The handler runs in a separate task, with its own [`Context`] (which has a copy of
+    /// the request's environment, `env`), until the connection closes.
+    /// Logs generated by the handler are sent to the logger after each message.
+    /// If the handler returns an error, the error is logged and the connection is closed
+    /// with status 1011 (internal error).
+    pub fn serve(
+        self,
+        handler: Box<dyn WebSocketHandler>,
+        logger: Box<dyn Logger>,
+        env: &Env,
+    ) -> Result<(), Error> {
+        let (tx, mut rx) = mpsc::unbounded::<SocketEvent>();
+        let on_message = {
+            let tx = tx.clone();
+            Closure::wrap(Box::new(move |ev: JsValue| {
+                let _ = tx.unbounded_send(SocketEvent::Message(message_from_event(&ev)));
+            }) as Box<dyn FnMut(JsValue)>)
+        };
+        let on_close = {
+            let tx = tx.clone();
+            Closure::wrap(Box::new(move |ev: JsValue| {
+                let code = get_prop(&ev, "code")
+                    .and_then(|v| v.as_f64())
+                    .unwrap_or(1005.0);
+                let reason = get_prop(&ev, "reason")
+                    .and_then(|v| v.as_string())
+                    .unwrap_or_default();
+                let _ = tx.unbounded_send(SocketEvent::Close(code as u16, reason));
+            }) as Box<dyn FnMut(JsValue)>)
+        };
+        let on_error = Closure::wrap(Box::new(move |_: JsValue| {
+            let _ = tx.unbounded_send(SocketEvent::Close(1006, "error".to_string()));
+        }) as Box<dyn FnMut(JsValue)>);
+        for (event, listener) in [
+            ("message", &on_message),
+            ("close", &on_close),
+            ("error", &on_error),
+        ]
+        .iter()
+        {
+            call_method(
+                &self.inner,
+                "addEventListener",
+                &Array::of2(&JsValue::from_str(event), listener.as_ref()),
+            )?;
+        }
+        call_method(&self.inner, "accept", &Array::new())?;
+
+        let env = env.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let mut ctx = Context::default();
+            ctx.set_env(env);
+            while let Some(event) = rx.next().await {
+                let done = match event {
+                    SocketEvent::Message(msg) => {
+                        match handler.on_message(msg, &self, &mut ctx).await {
+                            Ok(()) => false,
+                            Err(e) => {
+                                log!(ctx, Severity::Error, _:"websocket", error: e);
+                                let _ = self.close(1011, "internal error");
+                                true
+                            }
+                        }
+                    }
+                    SocketEvent::Close(code, reason) => {
+                        handler.on_close(code, &reason, &mut ctx).await;
+                        true
+                    }
+                };
+                let logs = ctx.take_logs();
+                if !logs.is_empty() {
+                    if let Err(e) = logger.send("websocket", logs).await {
+                        crate::log_log_error(e);
+                    }
+                }
+                if done {
+                    break;
+                }
+            }
+            // listeners live as long as the connection. They are removed before the closures
+            // are dropped, so events after the server closes the socket aren't delivered to them.
+            for (event, listener) in [
+                ("message", &on_message),
+                ("close", &on_close),
+                ("error", &on_error),
+            ]
+            .iter()
+            {
+                let _ = call_method(
+                    &self.inner,
+                    "removeEventListener",
+                    &Array::of2(&JsValue::from_str(event), listener.as_ref()),
+                );
+            }
+        });
+        Ok(())
+    }
+
+    /// Returns the javascript WebSocket object
+    pub(crate) fn as_js(&self) -> &JsValue {
+        &self.inner
+    }
+}
+
+impl From<JsValue> for WebSocket {
+    fn from(inner: JsValue) -> WebSocket {
+        WebSocket { inner }
+    }
+}
+
+/// Trait for processing messages received on the server side of a [`WebSocketPair`]
+#[async_trait(?Send)]
+pub trait WebSocketHandler {
+    /// Process a message. Replies may be sent on `socket`.
+    async fn on_message(
+        &self,
+        msg: WsMessage,
+        socket: &WebSocket,
+        ctx: &mut Context,
+    ) -> Result<(), Error>;
+
+    /// Invoked when the connection is closed by the client, or due to a network error.
+    /// The default implementation does nothing.
+    async fn on_close(&self, _code: u16, _reason: &str, _ctx: &mut Context) {}
+}
+
+enum SocketEvent {
+    Message(WsMessage),
+    Close(u16, String),
+}
+
+/// Extracts data from a javascript MessageEvent, which may be a string or ArrayBuffer
+fn message_from_event(ev: &JsValue) -> WsMessage {
+    let data = get_prop(ev, "data").unwrap_or_default();
+    match data.as_string() {
+        Some(text) => WsMessage::Text(text),
+        None => WsMessage::Binary(Uint8Array::new(&data).to_vec()),
+    }
+}
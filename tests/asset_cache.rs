@@ -0,0 +1,67 @@
+use bytes::Bytes;
+use wasm_service::{AssetCache, AssetCacheStats, AssetMetadata};
+
+fn md(path: &str, modified: u64) -> AssetMetadata {
+    AssetMetadata {
+        path: path.to_string(),
+        modified,
+        size: 0,
+    }
+}
+
+#[test]
+fn cache_hits_and_misses() {
+    let cache = AssetCache::new(10, 1000);
+    assert_eq!(cache.get(&md("a.css", 1)), None);
+    cache.insert(&md("a.css", 1), Bytes::from_static(b"body {}"));
+    assert_eq!(
+        cache.get(&md("a.css", 1)),
+        Some(Bytes::from_static(b"body {}"))
+    );
+
+    // a newer version of the asset is a miss, and replaces the old one
+    assert_eq!(cache.get(&md("a.css", 2)), None);
+    cache.insert(&md("a.css", 2), Bytes::from_static(b"p {}"));
+    assert_eq!(
+        cache.get(&md("a.css", 2)),
+        Some(Bytes::from_static(b"p {}"))
+    );
+
+    assert_eq!(
+        cache.stats(),
+        AssetCacheStats {
+            hits: 2,
+            misses: 2,
+            evictions: 0,
+            entries: 1,
+            bytes: 4,
+        }
+    );
+}
+
+#[test]
+fn cache_eviction() {
+    let cache = AssetCache::new(2, 10);
+    cache.insert(&md("a", 1), Bytes::from_static(b"aaa"));
+    cache.insert(&md("b", 1), Bytes::from_static(b"bbb"));
+    // use "a" so that "b" is least recently used
+    assert!(cache.get(&md("a", 1)).is_some());
+    cache.insert(&md("c", 1), Bytes::from_static(b"ccc"));
+    assert_eq!(cache.len(), 2);
+    assert!(cache.get(&md("b", 1)).is_none());
+    assert!(cache.get(&md("a", 1)).is_some());
+
+    // size limit
+    cache.insert(&md("d", 1), Bytes::from_static(b"dddddddd"));
+    assert_eq!(cache.len(), 1);
+    assert_eq!(cache.stats().bytes, 8);
+    assert_eq!(cache.stats().evictions, 3);
+
+    // too large to cache
+    cache.insert(&md("e", 1), Bytes::from(vec![0u8; 11]));
+    assert!(cache.get(&md("e", 1)).is_none());
+
+    cache.clear();
+    assert!(cache.is_empty());
+    assert_eq!(cache.stats().bytes, 0);
+}
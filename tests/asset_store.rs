@@ -0,0 +1,55 @@
+use futures::executor::block_on;
+use wasm_service::{AssetStore, MemoryAssets};
+
+#[test]
+fn memory_assets() {
+    let mut store = MemoryAssets::default();
+    store.insert("css/site.css", "body {}", 1000);
+
+    let md = store.lookup("css/site.css").unwrap().expect("found");
+    assert_eq!(md.size, 7);
+    assert_eq!(md.modified, 1000);
+    assert_eq!(&block_on(store.get(&md)).unwrap()[..], b"body {}");
+    assert_eq!(store.lookup("missing.css").unwrap(), None);
+
+    store.insert("css/print.css", "", 1000);
+    store.insert("index.html", "", 1000);
+    let mut paths = store
+        .list("css/")
+        .unwrap()
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert_eq!(paths, vec!["css/print.css", "css/site.css"]);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn dir_assets() {
+    use wasm_service::DirAssets;
+
+    let root = std::env::temp_dir().join(format!("wasm-service-assets-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("js")).unwrap();
+    std::fs::write(root.join("js/app.js"), "let x = 1;").unwrap();
+    let store = DirAssets::new(&root);
+
+    let md = store.lookup("js/app.js").unwrap().expect("found");
+    assert_eq!(md.path, "js/app.js");
+    assert_eq!(md.size, 10);
+    assert!(md.modified > 0);
+    assert_eq!(&block_on(store.get(&md)).unwrap()[..], b"let x = 1;");
+
+    // directories and paths outside the root are not found
+    assert_eq!(store.lookup("js").unwrap(), None);
+    assert_eq!(store.lookup("../etc/passwd").unwrap(), None);
+    assert_eq!(store.lookup("missing.js").unwrap(), None);
+
+    std::fs::write(root.join("index.html"), "").unwrap();
+    let listed = store.list("js/").unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].0, "js/app.js");
+    assert_eq!(store.list("").unwrap().len(), 2);
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
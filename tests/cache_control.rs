@@ -0,0 +1,36 @@
+use std::time::Duration;
+use wasm_service::CacheControl;
+
+#[test]
+fn cache_control_format() {
+    assert_eq!(CacheControl::new().to_string(), "");
+    assert_eq!(CacheControl::new().no_cache().to_string(), "no-cache");
+    assert_eq!(
+        CacheControl::new()
+            .public()
+            .max_age(Duration::from_secs(31536000))
+            .immutable()
+            .to_string(),
+        "public, max-age=31536000, immutable"
+    );
+    assert_eq!(
+        CacheControl::new()
+            .private()
+            .must_revalidate()
+            .max_age(Duration::from_secs(60))
+            .stale_while_revalidate(Duration::from_secs(30))
+            .to_string(),
+        "private, must-revalidate, max-age=60, stale-while-revalidate=30"
+    );
+}
+
+#[test]
+fn cache_control_shared() {
+    let cc = CacheControl::new()
+        .public()
+        .s_maxage(Duration::from_secs(600));
+    assert!(cc.is_shared_cacheable());
+    assert_eq!(cc.get_s_maxage(), Some(600));
+    assert!(!CacheControl::new().private().is_shared_cacheable());
+    assert!(!CacheControl::new().no_store().is_shared_cacheable());
+}
@@ -98,3 +98,23 @@ fn response_headers() {
     assert!(sv.is_some(), "is-defined content-type");
     assert_eq!(sv.unwrap(), "application/json", "content-type value");
 }
+
+#[wasm_bindgen_test]
+fn response_body_stream() {
+    use wasm_service::Body;
+
+    let mut ctx = Context::default();
+    assert!(!ctx.response().is_stream());
+
+    let chunks = vec![
+        Ok(bytes::Bytes::from_static(b"hello ")),
+        Ok(bytes::Bytes::from_static(b"world")),
+    ];
+    ctx.response()
+        .body(Body::from_stream(futures::stream::iter(chunks)));
+    assert!(!ctx.response().is_unset());
+    assert!(ctx.response().is_stream());
+    assert!(!ctx.response().is_empty());
+    // contents of stream body are not available
+    assert_eq!(ctx.response().get_body().len(), 0);
+}
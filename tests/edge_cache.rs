@@ -0,0 +1,44 @@
+use bytes::Bytes;
+use futures::executor::block_on;
+use wasm_service::{CacheControl, CacheStore, CachedResponse, MemoryCache};
+
+#[test]
+fn memory_cache_store() {
+    let cache = MemoryCache::default();
+    let resp = CachedResponse {
+        status: 200,
+        headers: vec![("content-type".to_string(), "text/plain".to_string())],
+        body: Bytes::from_static(b"hello"),
+    };
+    block_on(async {
+        assert_eq!(cache.get("https://example.com/a").await.unwrap(), None);
+        cache
+            .put("https://example.com/a", resp.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            cache.get("https://example.com/a").await.unwrap(),
+            Some(resp)
+        );
+        assert_eq!(cache.len(), 1);
+        assert!(cache.delete("https://example.com/a").await.unwrap());
+        assert!(!cache.delete("https://example.com/a").await.unwrap());
+        assert!(cache.is_empty());
+    });
+}
+
+#[test]
+fn cache_control_parse() {
+    let cc = CacheControl::parse("Public, max-age=60, s-maxage=\"600\", foo=bar");
+    assert!(cc.is_shared_cacheable());
+    assert_eq!(cc.get_max_age(), Some(60));
+    assert_eq!(cc.shared_ttl(), Some(600));
+    assert_eq!(cc.to_string(), "public, max-age=60, s-maxage=600");
+
+    assert_eq!(
+        CacheControl::parse("private, max-age=60").shared_ttl(),
+        None
+    );
+    assert_eq!(CacheControl::parse("no-cache").shared_ttl(), None);
+    assert_eq!(CacheControl::parse("").shared_ttl(), None);
+}
@@ -0,0 +1,44 @@
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+#[cfg(target_arch = "wasm32")]
+mod test {
+    use wasm_bindgen::JsValue;
+    use wasm_bindgen_test::*;
+    use wasm_service::{Context, Env, Error};
+
+    fn test_env() -> Env {
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"GREETING".into(), &"hello".into()).unwrap();
+        js_sys::Reflect::set(&obj, &"API_KEY".into(), &"s3cret".into()).unwrap();
+        js_sys::Reflect::set(&obj, &"COUNT".into(), &JsValue::from_f64(3.0)).unwrap();
+        js_sys::Reflect::set(&obj, &"MY_KV".into(), &js_sys::Object::new()).unwrap();
+        Env::from(JsValue::from(obj))
+    }
+
+    #[wasm_bindgen_test]
+    fn env_vars() {
+        let env = test_env();
+        assert_eq!(env.var("GREETING").unwrap(), "hello");
+        assert_eq!(env.secret("API_KEY").unwrap(), "s3cret");
+
+        // non-string and missing values
+        assert_eq!(env.var("COUNT"), None);
+        assert_eq!(env.var("MISSING"), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn env_kv_binding() {
+        let env = test_env();
+        assert!(env.kv("MY_KV").is_ok());
+        assert!(matches!(env.kv("NOT_BOUND"), Err(Error::MissingBinding(_))));
+    }
+
+    #[wasm_bindgen_test]
+    fn context_env() {
+        let mut ctx = Context::default();
+        assert_eq!(ctx.env().var("GREETING"), None);
+
+        ctx.set_env(test_env());
+        assert_eq!(ctx.env().var("GREETING").unwrap(), "hello");
+    }
+}
@@ -0,0 +1,71 @@
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use wasm_service::HttpDate;
+
+// Sun, 06 Nov 1994 08:49:37 GMT
+const TS: u64 = 784111777;
+
+#[test]
+fn format_imf_fixdate() {
+    assert_eq!(
+        HttpDate::from(TS).to_string(),
+        "Sun, 06 Nov 1994 08:49:37 GMT"
+    );
+    assert_eq!(
+        HttpDate::from(0u64).to_string(),
+        "Thu, 01 Jan 1970 00:00:00 GMT"
+    );
+}
+
+#[test]
+fn parse_formats() {
+    for s in &[
+        "Sun, 06 Nov 1994 08:49:37 GMT",
+        "Sunday, 06-Nov-94 08:49:37 GMT",
+        "Sun Nov  6 08:49:37 1994",
+        "Sun, 06 Nov 1994 08:49:37 +0000",
+        " Sun, 06 Nov 1994 09:49:37 +0100 ",
+    ] {
+        assert_eq!(
+            HttpDate::from_str(s).map(|d| d.timestamp()),
+            Ok(TS),
+            "parsing {}",
+            s
+        );
+    }
+    assert!(HttpDate::from_str("").is_err());
+    assert!(HttpDate::from_str("yesterday").is_err());
+    assert!(HttpDate::from_str("Sun, 06 Nov 1994 08:49:37").is_err());
+
+    // round trip
+    let date = HttpDate::from(TS);
+    assert_eq!(HttpDate::from_str(&date.to_string()), Ok(date));
+}
+
+#[test]
+fn conversions() {
+    let date = HttpDate::from(TS);
+    let system = SystemTime::from(date);
+    assert_eq!(system, UNIX_EPOCH + Duration::from_secs(TS));
+    assert_eq!(HttpDate::from(system + Duration::from_millis(900)), date);
+    assert_eq!(
+        HttpDate::from(UNIX_EPOCH - Duration::from_secs(5)),
+        HttpDate::from(0u64)
+    );
+
+    let dt = chrono::DateTime::<chrono::Utc>::from(date);
+    assert_eq!(dt.timestamp(), TS as i64);
+    assert_eq!(HttpDate::from(dt), date);
+    assert_eq!(HttpDate::from(-10i64), HttpDate::from(0u64));
+}
+
+#[test]
+fn duration_arithmetic() {
+    let date = HttpDate::from(TS);
+    let later = date + Duration::from_secs(3600);
+    assert_eq!(later.timestamp(), TS + 3600);
+    assert_eq!(later - Duration::from_secs(3600), date);
+    assert_eq!(later.duration_since(date), Some(Duration::from_secs(3600)));
+    assert_eq!(date.duration_since(later), None);
+    assert_eq!(date - Duration::from_secs(TS + 1), HttpDate::from(0u64));
+}
@@ -0,0 +1,48 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use wasm_service::{AssetIndex, IndexBuilder, UploadManifest};
+
+#[test]
+fn build_index() {
+    let root = std::env::temp_dir().join(format!("wasm-service-index-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("css")).unwrap();
+    std::fs::create_dir_all(root.join(".git")).unwrap();
+    std::fs::write(root.join("index.html"), "<html></html>").unwrap();
+    std::fs::write(root.join("css/site.css"), "body {}").unwrap();
+    std::fs::write(root.join("css/site.css.map"), "{}").unwrap();
+    std::fs::write(root.join(".git/config"), "").unwrap();
+
+    let (index, manifest) = IndexBuilder::new(&root).exclude("*.map").build().unwrap();
+    assert_eq!(index.len(), 2);
+    let paths = manifest
+        .entries
+        .iter()
+        .map(|e| e.path.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(paths, vec!["css/site.css", "index.html"]);
+
+    let css = &manifest.entries[0];
+    assert_eq!(css.sha256.len(), 64);
+    assert_eq!(css.key, format!("css/site.{}.css", css.sha256));
+    assert_eq!(css.media_type.as_deref(), Some("text/css"));
+    assert_eq!(index["css/site.css"].path, css.key);
+    assert_eq!(index["css/site.css"].size, 7);
+
+    // index round-trips through bincode
+    let bin = IndexBuilder::serialize(&index).unwrap();
+    let decoded: AssetIndex = bincode::deserialize(&bin).unwrap();
+    assert_eq!(decoded, index);
+
+    let json = manifest.to_json().unwrap();
+    assert_eq!(UploadManifest::from_json(&json).unwrap(), manifest);
+
+    // change one file: only it is uploaded, and its old key is stale
+    std::fs::write(root.join("css/site.css"), "body { margin: 0 }").unwrap();
+    let (_, updated) = IndexBuilder::new(&root).exclude("*.map").build().unwrap();
+    let diff = updated.diff(manifest.entries.iter().map(|e| e.key.clone()));
+    assert_eq!(diff.upload.len(), 1);
+    assert_eq!(diff.upload[0].path, "css/site.css");
+    assert_eq!(diff.stale, vec![css.key.clone()]);
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,84 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use service_logging::{log, Severity};
+use wasm_service::{AckState, Context, Error, HttpDate, MessageBatch, QueueHandler, QueueMessage};
+
+#[derive(Debug, Deserialize)]
+struct Job {
+    n: u32,
+}
+
+/// Acks even jobs, retries odd jobs, fails on zero
+struct JobHandler {}
+#[async_trait(?Send)]
+impl QueueHandler for JobHandler {
+    type Message = Job;
+
+    async fn handle_batch(
+        &self,
+        batch: &mut MessageBatch<Job>,
+        ctx: &mut Context,
+    ) -> Result<(), Error> {
+        for msg in batch.messages_mut() {
+            match msg.body().n {
+                0 => return Err(Error::Other("zero".to_string())),
+                n if n % 2 == 0 => msg.ack(),
+                _ => msg.retry(),
+            }
+            log!(ctx, Severity::Info, id: msg.id(), n: msg.body().n);
+        }
+        Ok(())
+    }
+}
+
+fn test_batch(values: &[u32]) -> MessageBatch<Job> {
+    MessageBatch::new(
+        "jobs",
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, n)| QueueMessage::new(i.to_string(), HttpDate::from(1000u64), Job { n: *n }))
+            .collect(),
+    )
+}
+
+#[test]
+fn queue_batch_state() {
+    let mut batch = test_batch(&[1, 2]);
+    assert_eq!(batch.queue(), "jobs");
+    assert_eq!(batch.messages().len(), 2);
+    assert_eq!(batch.messages()[0].id(), "0");
+    assert_eq!(batch.messages()[0].attempts(), 1);
+    assert_eq!(batch.messages()[0].timestamp().timestamp(), 1000);
+    assert!(batch
+        .messages()
+        .iter()
+        .all(|m| m.state() == AckState::Pending));
+
+    batch.ack_all();
+    assert!(batch.messages().iter().all(|m| m.state() == AckState::Ack));
+
+    batch.retry_all();
+    assert!(batch
+        .messages()
+        .iter()
+        .all(|m| m.state() == AckState::Retry));
+}
+
+#[test]
+fn queue_handler_native() {
+    let mut ctx = Context::default();
+    let mut batch = test_batch(&[2, 3, 4]);
+    let handler = JobHandler {};
+
+    futures::executor::block_on(handler.handle_batch(&mut batch, &mut ctx)).expect("handler ok");
+    let states: Vec<AckState> = batch.messages().iter().map(|m| m.state()).collect();
+    assert_eq!(states, vec![AckState::Ack, AckState::Retry, AckState::Ack]);
+    assert_eq!(ctx.take_logs().len(), 3);
+
+    // handler error leaves remaining messages pending
+    let mut batch = test_batch(&[2, 0, 4]);
+    assert!(futures::executor::block_on(handler.handle_batch(&mut batch, &mut ctx)).is_err());
+    assert_eq!(batch.messages()[0].state(), AckState::Ack);
+    assert_eq!(batch.messages()[2].state(), AckState::Pending);
+}
@@ -25,6 +25,21 @@ mod test {
         assert_eq!(req.method(), Method::DELETE);
     }
 
+    #[wasm_bindgen_test]
+    fn req_is_head() {
+        let url = Url::parse("https://www.example.com").unwrap();
+        let req = Request::new(
+            Method::HEAD,
+            url.clone(),
+            web_sys::Headers::new().unwrap(),
+            None,
+        );
+        assert!(req.is_head());
+
+        let req = Request::new(Method::GET, url, web_sys::Headers::new().unwrap(), None);
+        assert!(!req.is_head());
+    }
+
     #[wasm_bindgen_test]
     fn req_url() {
         let req = Request::new(
@@ -63,6 +78,27 @@ mod test {
         assert_eq!(req.get_header("not-here"), None);
     }
 
+    #[wasm_bindgen_test]
+    fn req_websocket_upgrade() {
+        let headers = web_sys::Headers::new().expect("new");
+        headers.set("Upgrade", "WebSocket").expect("ok");
+        let req = Request::new(
+            Method::GET,
+            Url::parse("https://www.example.com/ws").unwrap(),
+            headers,
+            None,
+        );
+        assert!(req.is_websocket_upgrade());
+
+        let req = Request::new(
+            Method::GET,
+            Url::parse("https://www.example.com/ws").unwrap(),
+            web_sys::Headers::new().unwrap(),
+            None,
+        );
+        assert!(!req.is_websocket_upgrade());
+    }
+
     #[wasm_bindgen_test]
     fn req_body() {
         let ascii_text = "hello-world";
@@ -89,6 +125,39 @@ mod test {
         assert_eq!(body[1], 1);
     }
 
+    #[wasm_bindgen_test]
+    async fn req_body_buffered() {
+        use futures::StreamExt;
+
+        let mut req = Request::new(
+            Method::POST,
+            Url::parse("https://www.example.com").unwrap(),
+            web_sys::Headers::new().unwrap(),
+            Some(b"{\"x\":1}".to_vec()),
+        );
+        assert_eq!(req.content_length(), Some(7));
+        assert_eq!(req.buffer().await.unwrap(), b"{\"x\":1}");
+
+        // buffered body is returned as a single chunk
+        let chunks: Vec<_> = req.body_stream().collect().await;
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].as_ref().unwrap().as_ref(), b"{\"x\":1}");
+
+        req.set_max_body_size(Some(100));
+        let val: std::collections::HashMap<String, u32> = req.json().unwrap();
+        assert_eq!(val.get("x"), Some(&1));
+
+        // no body
+        let req = Request::new(
+            Method::GET,
+            Url::parse("https://www.example.com").unwrap(),
+            web_sys::Headers::new().unwrap(),
+            None,
+        );
+        assert!(req.is_empty());
+        assert_eq!(req.buffer().await.unwrap().len(), 0);
+    }
+
     #[wasm_bindgen_test]
     fn req_query() {
         let req = Request::new(
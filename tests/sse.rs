@@ -0,0 +1,50 @@
+use wasm_service::Event;
+
+#[test]
+fn sse_event_format() {
+    // data only
+    assert_eq!(Event::new("hello").to_string(), "data: hello\n\n");
+
+    // all fields
+    let ev = Event::new("hello").id("1").event("greeting").retry(5000);
+    assert_eq!(
+        ev.to_string(),
+        "id: 1\nevent: greeting\nretry: 5000\ndata: hello\n\n"
+    );
+
+    // multi-line data
+    assert_eq!(
+        Event::new("line1\nline2").to_string(),
+        "data: line1\ndata: line2\n\n"
+    );
+
+    // trailing and empty lines are kept; \r\n is the same as \n
+    assert_eq!(
+        Event::new("a\r\n\nb\n").to_string(),
+        "data: a\ndata:\ndata: b\ndata:\n\n"
+    );
+
+    // a lone \r is also a line break
+    assert_eq!(
+        Event::new("a\rb\r\rc").to_string(),
+        "data: a\ndata: b\ndata:\ndata: c\n\n"
+    );
+
+    // empty data
+    assert_eq!(
+        Event::new("").event("ping").to_string(),
+        "event: ping\ndata:\n\n"
+    );
+
+    // newlines are removed from single-line fields
+    assert_eq!(
+        Event::new("x").id("a\nb").to_string(),
+        "id: a b\ndata: x\n\n"
+    );
+}
+
+#[test]
+fn sse_event_json() {
+    let ev = Event::json(&vec![1, 2, 3]).unwrap();
+    assert_eq!(ev.to_string(), "data: [1,2,3]\n\n");
+}